@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use kaleido_lib::prelude::Lexer;
+use kaleido_lib::testgen::{generate, GenOptions};
+
+fn lexing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lex");
+    for statements in [10_000usize, 100_000] {
+        let source = generate(GenOptions { seed: 1, functions: statements / 100, statements: 100, max_depth: 3 });
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(statements), &source, |b, source| {
+            b.iter(|| Lexer::lexer_tokens(source.as_bytes()).map(|(_, tokens)| tokens.len()))
+        });
+    }
+    group.finish();
+}
+
+// A data file of a million numeric literals, half integers and half decimals.
+fn numbers(c: &mut Criterion) {
+    let source = (0..1_000_000u64)
+        .map(|i| if i % 2 == 0 { format!("{} ", i * 7919) } else { format!("{}.{} ", i, i % 1000) })
+        .collect::<String>();
+    let mut group = c.benchmark_group("lex_numbers");
+    group.throughput(Throughput::Elements(1_000_000));
+    group.sample_size(10);
+    group.bench_function("1M", |b| {
+        b.iter(|| Lexer::lexer_tokens(source.as_bytes()).map(|(_, tokens)| tokens.len()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lexing, numbers);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+// Prints the spanned tokens of a Kaleido source file, one per line.
+//
+//     cargo run --example tokenize -- path/to/file.kld
+
+use std::{env, fs, process};
+
+use kaleido_lib::prelude::*;
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: tokenize <file>");
+        process::exit(2);
+    };
+    let source = fs::read(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+
+    let (tokens, errors) = Lexer::lex_with_recovery(&source);
+    for (token, span) in &tokens {
+        println!("{}..{}\t{}", span.start, span.end, token);
+    }
+    for err in errors {
+        eprintln!("{}: {}", path, LocatedLexError::from_nom(&source, nom::Err::Failure(err)));
+    }
+}
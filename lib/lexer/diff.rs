@@ -0,0 +1,176 @@
+// Tooling only, not on the lexing path. Every index below is bounded by the
+// lengths the LCS table and the trimmed token slices were built from.
+#![allow(clippy::indexing_slicing)]
+
+use crate::lexer::error::LexError;
+use crate::lexer::lex::Lexer;
+use crate::lexer::span::{Span, SpannedToken};
+use crate::lexer::tokens::Token;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TokenChange {
+    Inserted { token: Token, new_span: Span },
+    Removed { token: Token, old_span: Span },
+    Changed { old: Token, new: Token, old_span: Span, new_span: Span },
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct TokenDiff {
+    pub changes: Vec<TokenChange>,
+}
+
+impl TokenDiff {
+    // Both sources lex to the same token stream, so they differ at most in
+    // whitespace and in how literals are spelled.
+    pub fn is_whitespace_only_change(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+enum Edit {
+    Keep,
+    Remove(usize),
+    Insert(usize),
+}
+
+// Edit script turning `old` into `new`, from a longest common subsequence
+// computed over the part that remains after trimming the common prefix and
+// suffix.
+fn edit_script(old: &[SpannedToken], new: &[SpannedToken]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a.0 == b.0).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.0 == b.0)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i].0 == new_mid[j].0 {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut script = (0..prefix).map(|_| Edit::Keep).collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i].0 == new_mid[j].0 {
+            script.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            script.push(Edit::Insert(prefix + j));
+            j += 1;
+        } else {
+            script.push(Edit::Remove(prefix + i));
+            i += 1;
+        }
+    }
+    script.extend((0..suffix).map(|_| Edit::Keep));
+    script
+}
+
+// Removals and insertions between the same two unchanged tokens are paired
+// up in order and reported as changes; whatever is left over stays a plain
+// removal or insertion.
+fn collect_changes(old: &[SpannedToken], new: &[SpannedToken], script: Vec<Edit>) -> Vec<TokenChange> {
+    let mut changes = vec![];
+    let mut removed = vec![];
+    let mut inserted = vec![];
+    let mut flush = |removed: &mut Vec<usize>, inserted: &mut Vec<usize>| {
+        let paired = removed.len().min(inserted.len());
+        for (r, i) in removed.iter().zip(inserted.iter()) {
+            let ((old_token, old_span), (new_token, new_span)) = (&old[*r], &new[*i]);
+            changes.push(TokenChange::Changed {
+                old: old_token.clone(),
+                new: new_token.clone(),
+                old_span: *old_span,
+                new_span: *new_span,
+            });
+        }
+        for r in &removed[paired..] {
+            changes.push(TokenChange::Removed { token: old[*r].0.clone(), old_span: old[*r].1 });
+        }
+        for i in &inserted[paired..] {
+            changes.push(TokenChange::Inserted { token: new[*i].0.clone(), new_span: new[*i].1 });
+        }
+        removed.clear();
+        inserted.clear();
+    };
+
+    for edit in script {
+        match edit {
+            Edit::Keep => flush(&mut removed, &mut inserted),
+            Edit::Remove(r) => removed.push(r),
+            Edit::Insert(i) => inserted.push(i),
+        }
+    }
+    flush(&mut removed, &mut inserted);
+    changes
+}
+
+// Lexes both sources and reports how their token streams differ, with spans
+// into the respective source.
+pub fn token_diff<'a>(old_src: &'a [u8], new_src: &'a [u8]) -> Result<TokenDiff, nom::Err<LexError<&'a [u8]>>> {
+    let (_, old) = Lexer::lexer_spanned_tokens(old_src)?;
+    let (_, new) = Lexer::lexer_spanned_tokens(new_src)?;
+    let script = edit_script(&old, &new);
+    Ok(TokenDiff { changes: collect_changes(&old, &new, script) })
+}
+
+// tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> TokenDiff {
+        token_diff(old.as_bytes(), new.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_reformatting_only() {
+        let d = diff("fn foo(a: int)->int{return a*2;}", "fn foo(a: int) -> int {\n    return a * 2;\n}\n");
+        assert!(d.is_whitespace_only_change());
+        assert_eq!(d, TokenDiff::default());
+    }
+
+    #[test]
+    fn test_renamed_identifier() {
+        let d = diff("let a = b + 1;", "let  a = count + 1;");
+        assert_eq!(d.changes, vec![TokenChange::Changed {
+            old: Token::Ident(String::from("b")),
+            new: Token::Ident(String::from("count")),
+            old_span: Span::new(8, 9),
+            new_span: Span::new(9, 14),
+        }]);
+        assert!(!d.is_whitespace_only_change());
+    }
+
+    #[test]
+    fn test_inserted_and_removed() {
+        let d = diff("a + b", "a + b * c");
+        assert_eq!(d.changes, vec![
+            TokenChange::Inserted { token: Token::Mult, new_span: Span::new(6, 7) },
+            TokenChange::Inserted { token: Token::Ident(String::from("c")), new_span: Span::new(8, 9) },
+        ]);
+
+        let d = diff("return -x;", "return;");
+        assert_eq!(d.changes, vec![
+            TokenChange::Removed { token: Token::Minus, old_span: Span::new(7, 8) },
+            TokenChange::Removed { token: Token::Ident(String::from("x")), old_span: Span::new(8, 9) },
+        ]);
+    }
+
+    #[test]
+    fn test_eof_moves_with_the_source() {
+        let d = diff("a", "a\n\n\n");
+        assert!(d.is_whitespace_only_change());
+    }
+}
@@ -1,5 +1,10 @@
 use std::{error, fmt};
 
+use nom::error::{ErrorKind, FromExternalError};
+
+use crate::lexer::span::Span;
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum ParseError {
     IntParseError(std::num::ParseIntError),
@@ -7,6 +12,164 @@ pub enum ParseError {
     StringParseError(std::str::Utf8Error),
     CharParseError(CharParseError),
     InvalidCharByteSequence(InvalidCharByteSequenceError),
+    InvalidEscape(EscapeError),
+    Unterminated(UnterminatedError),
+    TooDeeplyNested(NestingError),
+    InvalidSuffix(SuffixError),
+    InvalidNumber(NumberError),
+}
+
+#[derive(Debug)]
+pub enum EscapeError {
+    TruncatedHex,
+    HexOutOfRange(u8),
+    Unknown(char),
+    MalformedUnicode(String),
+    InvalidCodepoint(u32),
+}
+
+#[derive(Debug)]
+pub enum NumberError {
+    Overflow(String),
+    InvalidDigit { digit: char, radix: u32 },
+    MissingDigits { radix: u32 },
+    MisplacedSeparator(String),
+}
+
+#[derive(Debug)]
+pub enum SuffixError {
+    Unknown(String),
+    IntOnDecimal,
+}
+
+#[derive(Debug)]
+pub struct UnterminatedError {
+    what: &'static str,
+    closing: &'static str,
+}
+
+impl UnterminatedError {
+    pub fn new(what: &'static str, closing: &'static str) -> UnterminatedError {
+        UnterminatedError { what, closing }
+    }
+}
+
+#[derive(Debug)]
+pub struct NestingError {
+    what: &'static str,
+    limit: usize,
+}
+
+impl NestingError {
+    pub fn new(what: &'static str, limit: usize) -> NestingError {
+        NestingError { what, limit }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SpanError {
+    Inverted { start: usize, end: usize },
+    Overflow,
+}
+
+#[derive(Debug)]
+pub enum LexErrorKind {
+    Nom(ErrorKind),
+    Parse(ParseError),
+}
+
+impl ParseError {
+    // Stable diagnostic code, K01xx for the lexer. Tests and tooling match
+    // on these rather than on message text.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::IntParseError(_) | ParseError::FloatParseError(_) | ParseError::InvalidNumber(_) => "K0101",
+            ParseError::StringParseError(_) => "K0102",
+            ParseError::CharParseError(_) | ParseError::InvalidCharByteSequence(_) => "K0103",
+            ParseError::InvalidEscape(_) => "K0104",
+            ParseError::Unterminated(_) => "K0105",
+            ParseError::TooDeeplyNested(_) => "K0106",
+            ParseError::InvalidSuffix(_) => "K0107",
+        }
+    }
+}
+
+impl LexErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexErrorKind::Nom(_) => "K0100",
+            LexErrorKind::Parse(err) => err.code(),
+        }
+    }
+}
+
+// `input` starts at the offending text. `len` is how many bytes of it are
+// at fault, or 0 when the error only marks a position.
+#[derive(Debug)]
+pub struct LexError<I> {
+    pub input: I,
+    pub kind: LexErrorKind,
+    pub len: usize,
+}
+
+impl<I> LexError<I> {
+    pub fn new(input: I, err: ParseError) -> LexError<I> {
+        LexError { input, kind: LexErrorKind::Parse(err), len: 0 }
+    }
+
+    pub fn with_len(self, len: usize) -> LexError<I> {
+        LexError { len, ..self }
+    }
+}
+
+impl LexError<&[u8]> {
+    // Byte offset of the error within `source`, the slice lexing started
+    // from. None if the error does not point into it.
+    pub fn offset_in(&self, source: &[u8]) -> Option<usize> {
+        let start = source.as_ptr() as usize;
+        let at = self.input.as_ptr() as usize;
+        (start..=start + source.len()).contains(&at).then(|| at - start)
+    }
+}
+
+// A lexer error pinned to a byte offset, detached from the borrowed input.
+// Hard failures (a malformed escape, an overflowing literal) are not
+// recoverable: the lexer stops there instead of trying other rules.
+#[derive(Debug)]
+pub struct LocatedLexError {
+    pub offset: usize,
+    pub len: usize,
+    pub kind: LexErrorKind,
+    pub recoverable: bool,
+}
+
+impl LocatedLexError {
+    pub fn from_nom(source: &[u8], err: nom::Err<LexError<&[u8]>>) -> LocatedLexError {
+        let locate = |e: LexError<&[u8]>, recoverable| {
+            let offset = e.offset_in(source).unwrap_or(source.len());
+            LocatedLexError {
+                offset,
+                len: e.len.min(source.len() - offset),
+                kind: e.kind,
+                recoverable,
+            }
+        };
+        match err {
+            nom::Err::Error(e) => locate(e, true),
+            nom::Err::Failure(e) => locate(e, false),
+            nom::Err::Incomplete(_) => LocatedLexError {
+                offset: source.len(),
+                len: 0,
+                kind: LexErrorKind::Nom(ErrorKind::Eof),
+                recoverable: true,
+            },
+        }
+    }
+
+    // The offending bytes, empty when the error only marks a position.
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.len)
+    }
 }
 
 #[derive(Debug)]
@@ -16,7 +179,7 @@ pub struct CharParseError {
 
 impl CharParseError {
     pub fn new(c: &[u8; 4]) -> CharParseError {
-        CharParseError { data: c.clone() }
+        CharParseError { data: *c }
     }
 }
 
@@ -41,10 +204,89 @@ impl fmt::Display for ParseError {
             ParseError::StringParseError(ref err) => err.fmt(f),
             ParseError::CharParseError(ref err) => err.fmt(f),
             ParseError::InvalidCharByteSequence(ref err) => err.fmt(f),
+            ParseError::InvalidEscape(ref err) => err.fmt(f),
+            ParseError::Unterminated(ref err) => err.fmt(f),
+            ParseError::TooDeeplyNested(ref err) => err.fmt(f),
+            ParseError::InvalidSuffix(ref err) => err.fmt(f),
+            ParseError::InvalidNumber(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for EscapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EscapeError::TruncatedHex => write!(f, "\\x escape must be followed by exactly two hex digits"),
+            EscapeError::HexOutOfRange(v) => write!(f, "\\x{:02x} is out of range, \\x escapes only cover ASCII (\\x00 to \\x7f); write \\u{{{:x}}} for the character U+{:04X}", v, v, v),
+            EscapeError::Unknown(c) => write!(f, "Unknown escape sequence \\{}", c.escape_debug()),
+            EscapeError::MalformedUnicode(s) => write!(f, "Malformed unicode escape {}, expected \\u{{...}} with 1 to 6 hex digits", s),
+            EscapeError::InvalidCodepoint(v) => write!(f, "\\u{{{:x}}} is not a character, surrogates and values above 10ffff are not allowed", v),
+        }
+    }
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberError::Overflow(s) => write!(f, "Integer literal {} does not fit in 64 bits", s),
+            NumberError::InvalidDigit { digit, radix } => write!(f, "Digit {:?} is not valid in a base {} literal", digit, radix),
+            NumberError::MissingDigits { radix } => write!(f, "Base {} literal has no digits", radix),
+            NumberError::MisplacedSeparator(s) => write!(f, "Digit separators in {} must sit between two digits", s),
+        }
+    }
+}
+
+impl fmt::Display for SuffixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SuffixError::Unknown(s) => write!(f, "Unknown numeric suffix `{}`, expected `i` or `f`", s),
+            SuffixError::IntOnDecimal => write!(f, "Decimal literal cannot have an `i` suffix"),
         }
     }
 }
 
+impl fmt::Display for UnterminatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unterminated {}, expected closing {}", self.what, self.closing)
+    }
+}
+
+impl fmt::Display for NestingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nested deeper than {} levels", self.what, self.limit)
+    }
+}
+
+impl fmt::Display for SpanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpanError::Inverted { start, end } => write!(f, "Span start {} is past its end {}", start, end),
+            SpanError::Overflow => write!(f, "Span offset out of range"),
+        }
+    }
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexErrorKind::Nom(kind) => write!(f, "Lexer error: {}", kind.description()),
+            LexErrorKind::Parse(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl<I> fmt::Display for LexError<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl fmt::Display for LocatedLexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
 impl fmt::Display for InvalidCharByteSequenceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Char sequence should be between 1 and 4 bytes long, but was {}", self.len_was)
@@ -74,6 +316,92 @@ impl error::Error for ParseError {
     }
 }
 
+impl error::Error for EscapeError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        self.source()
+    }
+}
+
+impl error::Error for NumberError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        self.source()
+    }
+}
+
+impl error::Error for SuffixError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        self.source()
+    }
+}
+
+impl error::Error for UnterminatedError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        self.source()
+    }
+}
+
+impl error::Error for NestingError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        self.source()
+    }
+}
+
+impl error::Error for SpanError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        self.source()
+    }
+}
+
+impl<I: fmt::Debug> error::Error for LexError<I> {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self.kind {
+            LexErrorKind::Parse(ref err) => Some(err),
+            _ => self.source()
+        }
+    }
+}
+
+impl error::Error for LocatedLexError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self.kind {
+            LexErrorKind::Parse(ref err) => Some(err),
+            _ => self.source()
+        }
+    }
+}
+
 impl error::Error for InvalidCharByteSequenceError {
     fn description(&self) -> &str {
         "description() is deprecated; use Display"
@@ -124,4 +452,52 @@ impl From<CharParseError> for ParseError {
     fn from(err: CharParseError) -> ParseError {
         ParseError::CharParseError(err)
     }
+}
+
+impl From<EscapeError> for ParseError {
+    fn from(err: EscapeError) -> ParseError {
+        ParseError::InvalidEscape(err)
+    }
+}
+
+impl From<NumberError> for ParseError {
+    fn from(err: NumberError) -> ParseError {
+        ParseError::InvalidNumber(err)
+    }
+}
+
+impl From<SuffixError> for ParseError {
+    fn from(err: SuffixError) -> ParseError {
+        ParseError::InvalidSuffix(err)
+    }
+}
+
+impl From<UnterminatedError> for ParseError {
+    fn from(err: UnterminatedError) -> ParseError {
+        ParseError::Unterminated(err)
+    }
+}
+
+impl From<NestingError> for ParseError {
+    fn from(err: NestingError) -> ParseError {
+        ParseError::TooDeeplyNested(err)
+    }
+}
+
+// nom error implementation
+
+impl<I> nom::error::ParseError<I> for LexError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        LexError { input, kind: LexErrorKind::Nom(kind), len: 0 }
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> FromExternalError<I, ParseError> for LexError<I> {
+    fn from_external_error(input: I, _: ErrorKind, err: ParseError) -> Self {
+        LexError::new(input, err)
+    }
 }
\ No newline at end of file
@@ -1,21 +1,25 @@
+use std::mem;
 use std::str;
+use std::time::{Duration, Instant};
 
 use crate::lexer::tokens::*;
 use crate::lexer::error::*;
+use crate::lexer::span::{Span, SpannedToken};
 
 use nom::branch::alt;
-use nom::character::complete::one_of;
-use nom::combinator::peek;
-use nom::combinator::{map, map_res, recognize, opt};
-use nom::bytes::complete::{tag, take};
+use nom::combinator::{consumed, map, map_res, recognize, opt, not, peek};
+use nom::bytes::complete::{tag, tag_no_case, take, take_till, take_while, take_while_m_n};
+use nom::error::{ErrorKind, ParseError as NomParseError};
 use nom::*;
 use nom::multi::{many0, many1};
-use nom::sequence::{delimited, pair, tuple};
-use nom::character::complete::{char, alpha1, alphanumeric1, digit1, multispace0};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::character::complete::{char, alpha1, alphanumeric1, digit1, line_ending, multispace0, satisfy, space0};
+
+type LexResult<'a, T> = IResult<&'a [u8], T, LexError<&'a [u8]>>;
 
 macro_rules! syntax {
     ($fn_name: ident, $tag_string: literal, $output_tok: expr) => {
-        fn $fn_name<'a>(s: &'a [u8]) -> IResult<&[u8], Token> {
+        fn $fn_name(s: &[u8]) -> LexResult<'_, Token> {
             map(tag($tag_string), |_| $output_tok)(s)
         }
     };
@@ -39,7 +43,7 @@ syntax! {lt_operator, "<", Token::LessThan}
 syntax! {assign_operator, "=", Token::Assign}
 syntax! {function_return_operator, "->", Token::FunctionReturn}
 
-fn lex_operator(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_operator(input: &[u8]) -> LexResult<'_, Token> {
     alt((
         equal_operator,
         not_equal_operator,
@@ -71,7 +75,7 @@ syntax! {rbrace_punctuation, "}", Token::RBrace}
 syntax! {lbracket_punctuation, "[", Token::LBracket}
 syntax! {rbracket_punctuation, "]", Token::RBracket}
 
-fn lex_punctuation(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_punctuation(input: &[u8]) -> LexResult<'_, Token> {
     alt((
         semicolon_punctuation,
         colon_punctuation,
@@ -96,7 +100,7 @@ syntax! {rshift_boolean_operation, ">>", Token::RShift}
 syntax! {and_logic_operation, "&&", Token::LogicAnd}
 syntax! {or_logic_operation, "||", Token::LogicOr}
 
-fn lex_boolean_operation(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_boolean_operation(input: &[u8]) -> LexResult<'_, Token> {
     alt((
         and_boolean_operation,
         or_boolean_operation,
@@ -106,50 +110,296 @@ fn lex_boolean_operation(input: &[u8]) -> IResult<&[u8], Token> {
     ))(input)
 }
 
-fn lex_logic_operation(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_logic_operation(input: &[u8]) -> LexResult<'_, Token> {
     alt((
         and_logic_operation,
         or_logic_operation,
     ))(input)
 }
 
-// strings
+// The shorter symbol each two-character operator rule above wins over by
+// longest match, for Lexer::explain_at. Every other token beats nothing.
+fn rejected_candidate(token: &Token) -> Option<Token> {
+    match token {
+        Token::Equal => Some(Token::Assign),
+        Token::NotEqual => Some(Token::Not),
+        Token::Exp => Some(Token::Mult),
+        Token::FunctionReturn => Some(Token::Minus),
+        Token::GreaterThanEqual => Some(Token::GreaterThan),
+        Token::LessThanEqual => Some(Token::LessThan),
+        Token::LShift => Some(Token::LessThan),
+        Token::RShift => Some(Token::GreaterThan),
+        Token::LogicAnd => Some(Token::BooleanAnd),
+        Token::LogicOr => Some(Token::BooleanOr),
+        _ => None,
+    }
+}
+
+// Whether the bytes next to a two-character operator could pair up with
+// its own into another one, so that a different split was plausible: the
+// `>` and `=` of `>>=` also spell `>=`, while `x != y` has one reading.
+fn contested(bytes: &[u8], span: Span) -> bool {
+    let forms_operator = |a: u8, b: u8| {
+        let pair = [a, b];
+        let lexed = alt((lex_logic_operation, lex_boolean_operation, lex_operator))(&pair[..]);
+        matches!(lexed, Ok((rest, token)) if rest.is_empty() && rejected_candidate(&token).is_some())
+    };
+    let spelling = bytes.get(span.start..span.end).unwrap_or_default();
+    let before = span.start.checked_sub(1).and_then(|i| bytes.get(i)).zip(spelling.first());
+    let after = spelling.last().zip(bytes.get(span.end));
+    [before, after].into_iter().flatten().any(|(a, b)| forms_operator(*a, *b))
+}
+
+// escapes
 
-fn concat_slice_and_vec(s: &[u8], v: Vec<u8>) -> Vec<u8> {
-    let mut vec_out = s.to_vec();
-    vec_out.extend(&v);
-    vec_out
+// A failure covering the escape from its backslash at `start` up to `rest`.
+fn escape_failure<'a>(start: &'a [u8], rest: &[u8], err: EscapeError) -> nom::Err<LexError<&'a [u8]>> {
+    nom::Err::Failure(LexError::new(start, err.into()).with_len(start.len() - rest.len()))
 }
 
+fn hex_escape<'a>(start: &'a [u8], input: &'a [u8]) -> LexResult<'a, char> {
+    let (i1, digits) = take_while_m_n(2, 2, |b: u8| b.is_ascii_hexdigit())(input).map_err(|_: nom::Err<LexError<&[u8]>>| {
+        let present = input.iter().take_while(|b| b.is_ascii_hexdigit()).count();
+        escape_failure(start, input.get(present..).unwrap_or_default(), EscapeError::TruncatedHex)
+    })?;
+    let value = digits.iter().fold(0u8, |acc, d| (acc << 4) | (*d as char).to_digit(16).unwrap_or(0) as u8);
+    if !value.is_ascii() {
+        return Err(escape_failure(start, i1, EscapeError::HexOutOfRange(value)))
+    }
+    Ok((i1, char::from(value)))
+}
+
+// `\u{...}` with one to six hex digits naming a char. Malformed escapes
+// report the text up to where they went wrong.
+fn unicode_escape<'a>(start: &'a [u8], input: &'a [u8]) -> LexResult<'a, char> {
+    let malformed = |rest: &[u8]| {
+        let text = start.get(..start.len() - rest.len()).unwrap_or_default();
+        escape_failure(start, rest, EscapeError::MalformedUnicode(String::from_utf8_lossy(text).into_owned()))
+    };
+    let (i1, _) = char('{')(input).map_err(|_: nom::Err<LexError<&[u8]>>| malformed(input))?;
+    let (i2, digits) = take_while(|b: u8| b.is_ascii_hexdigit())(i1)?;
+    let (i3, _) = char('}')(i2).map_err(|_: nom::Err<LexError<&[u8]>>| malformed(i2))?;
+    if digits.is_empty() || digits.len() > 6 {
+        return Err(malformed(i3))
+    }
+    let value = digits.iter().fold(0u32, |acc, d| (acc << 4) | (*d as char).to_digit(16).unwrap_or(0));
+    let c = char::from_u32(value).ok_or_else(|| escape_failure(start, i3, EscapeError::InvalidCodepoint(value)))?;
+    Ok((i3, c))
+}
+
+// Decodes the escape sequence starting at the backslash. Shared by string and char literals.
+fn escape_sequence(input: &[u8]) -> LexResult<'_, char> {
+    let (i1, _) = char('\\')(input)?;
+    let (i2, c) = take(1usize)(i1)?;
+    match c {
+        b"\"" => Ok((i2, '"')),
+        b"'" => Ok((i2, '\'')),
+        b"\\" => Ok((i2, '\\')),
+        b"$" => Ok((i2, '$')),
+        b"n" => Ok((i2, '\n')),
+        b"t" => Ok((i2, '\t')),
+        b"r" => Ok((i2, '\r')),
+        b"0" => Ok((i2, '\0')),
+        b"x" => hex_escape(input, i2),
+        b"u" => unicode_escape(input, i2),
+        [_] => {
+            // Cover the whole character after the backslash, not just its first byte.
+            let (rest, _) = take_while_m_n(0, 3, |b: u8| (b & 0xc0) == 0x80)(i2)?;
+            let c = i1.get(..i1.len() - rest.len())
+                .and_then(|bytes| str::from_utf8(bytes).ok())
+                .and_then(|s| s.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            Err(escape_failure(input, rest, EscapeError::Unknown(c)))
+        }
+        _ => Err(nom::Err::Error(LexError::from_error_kind(input, ErrorKind::Escaped))),
+    }
+}
+
+fn encode_char(c: char) -> Vec<u8> {
+    c.to_string().into_bytes()
+}
+
+// strings
+
 fn convert_slice_to_utf8(s: &[u8]) -> Result<String, ParseError> {
     str::from_utf8(s).map(|s| s.to_owned()).map_err(|e| e.into())
 }
 
-fn string_body(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
-    let (i1, c1) = take(1usize)(input)?;
-    match c1.as_bytes() {
-        b"\"" => Ok((input, vec![])),
-        b"\\" => {
-            match peek(one_of(r#""'\"#)).parse(i1) {
-                Ok(_) => {
-                    let (i2, c2) = take(1usize)(i1)?;
-                    string_body(i2).map(|(s, done)| (s, concat_slice_and_vec(c2, done)))
-                },
-                Result::Err(e) => Err(e),
+// Strings nested inside interpolations recurse through the lexer, so their
+// depth is capped to keep adversarial input from exhausting the stack.
+const MAX_INTERPOLATION_DEPTH: usize = 32;
+
+// The tokens of a `${...}` interpolation, up to the `}` matching its opening
+// brace. Balanced braces and nested string literals may appear inside.
+fn interpolation(input: &[u8], depth: usize) -> LexResult<'_, Vec<Token>> {
+    let (mut rest, _) = tag("${")(input)?;
+    let mut tokens = vec![];
+    let mut braces = 0usize;
+    loop {
+        let (i1, _) = trivia(rest)?;
+        if i1.is_empty() {
+            return Err(nom::Err::Failure(LexError::new(input, UnterminatedError::new("string interpolation", "}").into())))
+        }
+        let (i2, token) = alt((|i| lex_nested_string(i, depth + 1), |i| lex_token_after(tokens.last(), i)))(i1)?;
+        match token {
+            Token::LBrace => braces += 1,
+            Token::RBrace if braces == 0 => return Ok((i2, tokens)),
+            Token::RBrace => braces -= 1,
+            _ => {}
+        }
+        tokens.push(token);
+        rest = i2;
+    }
+}
+
+fn flush_string_literal<'a>(input: &'a [u8], literal: &mut Vec<u8>, parts: &mut Vec<StringPart>) -> Result<(), nom::Err<LexError<&'a [u8]>>> {
+    if !literal.is_empty() {
+        let s = convert_slice_to_utf8(literal).map_err(|e| nom::Err::Error(LexError::new(input, e)))?;
+        parts.push(StringPart::Literal(s));
+        literal.clear();
+    }
+    Ok(())
+}
+
+fn string_body(input: &[u8], depth: usize) -> LexResult<'_, Vec<StringPart>> {
+    let mut parts = vec![];
+    let mut literal = vec![];
+    let mut rest = input;
+    loop {
+        match rest {
+            [b'"', ..] => break,
+            [b'\\', ..] => {
+                let (i1, c) = escape_sequence(rest)?;
+                literal.extend(encode_char(c));
+                rest = i1;
             }
+            [b'$', b'{', ..] => {
+                flush_string_literal(input, &mut literal, &mut parts)?;
+                let (i1, tokens) = interpolation(rest, depth)?;
+                parts.push(StringPart::Expr(tokens));
+                rest = i1;
+            }
+            [b, tail @ ..] => {
+                literal.push(*b);
+                rest = tail;
+            }
+            [] => return Err(nom::Err::Error(LexError::from_error_kind(rest, ErrorKind::Eof))),
         }
-        c => string_body(i1).map(|(s, done)| (s, concat_slice_and_vec(c, done))),
     }
+    flush_string_literal(input, &mut literal, &mut parts)?;
+    Ok((rest, parts))
 }
 
-fn input_to_string(input: &[u8]) -> IResult<&[u8], String> {
-    map_res(delimited(char('"'), string_body, char('"')), |s| {
-        convert_slice_to_utf8(s.as_slice())
-    })(input)
+fn input_to_string(input: &[u8], depth: usize) -> LexResult<'_, Vec<StringPart>> {
+    delimited(char('"'), |i| string_body(i, depth), char('"'))(input)
+}
+
+// Strings without interpolations stay plain string literals.
+fn string_token(parts: Vec<StringPart>) -> Token {
+    match parts.first() {
+        None => Token::StringLiteral(String::new()),
+        Some(StringPart::Literal(s)) if parts.len() == 1 => Token::StringLiteral(s.clone()),
+        _ => Token::InterpolatedString(parts),
+    }
+}
+
+fn lex_string(input: &[u8]) -> LexResult<'_, Token> {
+    map(|i| input_to_string(i, 0), string_token)(input)
 }
 
-fn lex_string(input: &[u8]) -> IResult<&[u8], Token> {
-    map(input_to_string, Token::StringLiteral)(input)
+fn lex_nested_string(input: &[u8], depth: usize) -> LexResult<'_, Token> {
+    if depth > MAX_INTERPOLATION_DEPTH {
+        let err = NestingError::new("string interpolation", MAX_INTERPOLATION_DEPTH);
+        return Err(nom::Err::Failure(LexError::new(input, err.into())))
+    }
+    map(|i| input_to_string(i, depth), string_token)(input)
+}
+
+// multi-line strings
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(|b| *b == b' ' || *b == b'\t')
+}
+
+fn indentation(line: &[u8]) -> usize {
+    line.iter().take_while(|b| **b == b' ' || **b == b'\t').count()
+}
+
+// Everything up to the closing """, skipping over escaped characters.
+fn triple_quoted_raw_body<'a>(start: &'a [u8], input: &'a [u8]) -> LexResult<'a, &'a [u8]> {
+    let mut rest = input;
+    while !rest.starts_with(b"\"\"\"") {
+        rest = match rest {
+            [b'\\', _, tail @ ..] | [_, tail @ ..] => tail,
+            [] => {
+                let err = UnterminatedError::new("string literal", "\"\"\"");
+                return Err(nom::Err::Failure(LexError::new(start, err.into())))
+            }
+        };
+    }
+    Ok((rest, input.get(..input.len() - rest.len()).unwrap_or_default()))
+}
+
+fn decode_triple_quoted_line<'a>(line: &'a [u8], out: &mut Vec<u8>) -> Result<(), nom::Err<LexError<&'a [u8]>>> {
+    let mut rest = line;
+    loop {
+        match rest {
+            [b'\\', ..] => {
+                let (r, c) = escape_sequence(rest).map_err(|e| match e {
+                    nom::Err::Error(e) => nom::Err::Failure(e),
+                    e => e,
+                })?;
+                out.extend(encode_char(c));
+                rest = r;
+            }
+            [b, tail @ ..] => {
+                out.push(*b);
+                rest = tail;
+            }
+            [] => return Ok(()),
+        }
+    }
+}
+
+// A line break right after the opening delimiter and the line holding the
+// closing delimiter are dropped, as is the indentation common to all
+// non-blank lines. Escapes are decoded after the indentation is stripped.
+fn input_to_triple_quoted_string(input: &[u8]) -> LexResult<'_, String> {
+    let (i1, _) = tag("\"\"\"")(input)?;
+    let (i2, raw) = triple_quoted_raw_body(input, i1)?;
+    let (i3, _) = tag("\"\"\"")(i2)?;
+
+    let mut lines = raw
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect::<Vec<&[u8]>>();
+    if lines.len() > 1 && lines.first().is_some_and(|l| is_blank(l)) {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && lines.last().is_some_and(|l| is_blank(l)) {
+        lines.pop();
+    }
+    let indent = lines.iter()
+        .filter(|l| !is_blank(l))
+        .map(|l| indentation(l))
+        .min()
+        .unwrap_or(0);
+
+    let mut out = vec![];
+    for (n, line) in lines.iter().enumerate() {
+        if n > 0 {
+            out.push(b'\n');
+        }
+        if !is_blank(line) {
+            decode_triple_quoted_line(line.get(indent..).unwrap_or_default(), &mut out)?;
+        }
+    }
+    let s = convert_slice_to_utf8(&out).map_err(|e| nom::Err::Failure(LexError::new(input, e)))?;
+    Ok((i3, s))
+}
+
+fn lex_triple_quoted_string(input: &[u8]) -> LexResult<'_, Token> {
+    map(input_to_triple_quoted_string, Token::StringLiteral)(input)
 }
 
 // chars
@@ -159,46 +409,50 @@ fn convert_slice_to_char(s: &[u8]) -> Result<char, ParseError> {
         return Err(InvalidCharByteSequenceError::new(s.len()).into())
     }
 
-    let chars = str::from_utf8(&s[..])?.chars().collect::<Vec<char>>();
-    if chars.len() != 1 {
-        let mut buffer: [u8; 4] = [0, 0, 0, 0];
-        s[..].iter().enumerate().for_each(|(i, v)| { buffer[i] = *v });
-        return Err(CharParseError::new(&buffer).into())
+    let mut chars = str::from_utf8(s)?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => {
+            let mut buffer: [u8; 4] = [0, 0, 0, 0];
+            buffer.iter_mut().zip(s).for_each(|(b, v)| *b = *v);
+            Err(CharParseError::new(&buffer).into())
+        }
     }
-
-    Ok(chars[0])
 }
 
-fn char_body(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
-    let (i1, c1) = take(1usize)(input)?;
-    match c1.as_bytes() {
-        b"'"  => Ok((input, vec![])),
-        b"\\" => {
-            match peek(one_of(r#""'\"#)).parse(i1) {
-                Ok(_) => {
-                    let (i2, c2) = take(1usize)(i1)?;
-                    char_body(i2).map(|(s, done)| (s, concat_slice_and_vec(c2, done)))
-                },
-                Result::Err(e) => Err(e),
+fn char_body(input: &[u8]) -> LexResult<'_, Vec<u8>> {
+    let mut body = vec![];
+    let mut rest = input;
+    loop {
+        match rest {
+            [b'\'', ..] => return Ok((rest, body)),
+            [b'\\', ..] => {
+                let (i1, c) = escape_sequence(rest)?;
+                body.extend(encode_char(c));
+                rest = i1;
+            }
+            [b, tail @ ..] => {
+                body.push(*b);
+                rest = tail;
             }
+            [] => return Err(nom::Err::Error(LexError::from_error_kind(rest, ErrorKind::Eof))),
         }
-        c => char_body(i1).map(|(s, done)| (s, concat_slice_and_vec(c, done)))
     }
 }
 
-fn input_to_char(input: &[u8]) -> IResult<&[u8], char> {
+fn input_to_char(input: &[u8]) -> LexResult<'_, char> {
     map_res(delimited(tag("'"), char_body, tag("'")), |s| {
         convert_slice_to_char(s.as_slice())
     })(input)
 }
 
-fn lex_char(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_char(input: &[u8]) -> LexResult<'_, Token> {
     map(input_to_char, Token::CharLiteral)(input)
 }
 
 // reserved words and identifiers
 
-fn ident_underscore_prefix(input: &[u8]) -> IResult<&[u8], &[u8]> {
+fn ident_underscore_prefix(input: &[u8]) -> LexResult<'_, &[u8]> {
     recognize(
         tuple((
             many1(tag("_")),
@@ -208,7 +462,7 @@ fn ident_underscore_prefix(input: &[u8]) -> IResult<&[u8], &[u8]> {
     )(input)
 }
 
-fn ident_alpha_prefix(input: &[u8]) -> IResult<&[u8], &[u8]> {
+fn ident_alpha_prefix(input: &[u8]) -> LexResult<'_, &[u8]> {
     recognize(
         pair(
             alpha1,
@@ -217,7 +471,7 @@ fn ident_alpha_prefix(input: &[u8]) -> IResult<&[u8], &[u8]> {
     )(input)
 }
 
-fn lex_ident_or_reserved(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_ident_or_reserved(input: &[u8]) -> LexResult<'_, Token> {
     map_res(
         recognize(
             alt((
@@ -226,6 +480,7 @@ fn lex_ident_or_reserved(input: &[u8]) -> IResult<&[u8], Token> {
             ))
         ), |i| {
             let s = convert_slice_to_utf8(i);
+            // Spelled out separately from KEYWORDS; a test keeps the two in sync.
             s.map(|syntax| match syntax.as_str() {
                 "let" => Token::Let,
                 "mut" => Token::Mut,
@@ -239,6 +494,7 @@ fn lex_ident_or_reserved(input: &[u8]) -> IResult<&[u8], Token> {
                 "break" => Token::Break,
                 "true" => Token::BoolLiteral(true),
                 "false" => Token::BoolLiteral(false),
+                _ if RESERVED_WORDS.contains(&syntax.as_str()) => Token::ReservedWord(syntax),
                 _ => Token::Ident(syntax)
             })
         })(input)
@@ -246,65 +502,261 @@ fn lex_ident_or_reserved(input: &[u8]) -> IResult<&[u8], Token> {
 
 // numbers
 
+// Digits with optional `_` separators, `1_000_000`. Trailing and doubled
+// separators are taken too, for check_separators to reject.
+fn digit_groups(input: &[u8]) -> LexResult<'_, &[u8]> {
+    recognize(pair(digit1, take_while(|b: u8| b.is_ascii_digit() || b == b'_')))(input)
+}
+
+// Each `_` needs a digit on both sides: `_1`, `1_` and `1__0` are errors.
+// `-` and `.` split a literal into separately checked groups.
+// The error quotes `literal`, the whole literal `digits` were taken from.
+fn check_separators(digits: &[u8], literal: &[u8]) -> Result<(), NumberError> {
+    let misplaced = digits.split(|b| *b == b'-' || *b == b'.')
+        .filter(|part| part.contains(&b'_'))
+        .any(|part| part.split(|b| *b == b'_').any(|group| group.is_empty()));
+    if misplaced {
+        Err(NumberError::MisplacedSeparator(String::from_utf8_lossy(literal).into_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+// Accumulates straight from the bytes, without going through a String.
+// The value is built up negatively so that i64::MIN is representable.
 fn convert_slice_to_number(s: &[u8]) -> Result<i64, ParseError> {
-    let r = convert_slice_to_utf8(s)?;
-    let i = str::parse::<i64>(r.as_str())?;
-    Ok(i)
+    let (negative, unsigned) = match s {
+        [b'-', rest @ ..] => (true, rest),
+        _ => (false, s),
+    };
+    let (radix, digits) = match unsigned {
+        [b'0', b'b' | b'B', rest @ ..] => (2, rest),
+        [b'0', b'o' | b'O', rest @ ..] => (8, rest),
+        _ => (10, unsigned),
+    };
+    if digits.is_empty() {
+        return Err(NumberError::MissingDigits { radix }.into());
+    }
+    check_separators(digits, s)?;
+    let overflow = || NumberError::Overflow(String::from_utf8_lossy(s).into_owned());
+    let mut n: i64 = 0;
+    for d in digits.iter().filter(|d| **d != b'_') {
+        let d = (*d as char).to_digit(radix).ok_or(NumberError::InvalidDigit { digit: *d as char, radix })?;
+        n = n.checked_mul(i64::from(radix)).and_then(|n| n.checked_sub(i64::from(d))).ok_or_else(overflow)?;
+    }
+    if negative {
+        Ok(n)
+    } else {
+        Ok(n.checked_neg().ok_or_else(overflow)?)
+    }
+}
 
+// Fails hard on overflow, rather than retrying the digits as other tokens.
+// A `0b` or `0o` literal takes every letter and digit after the prefix up
+// to an `i` or `f` suffix, so `0b12` is one bad literal instead of `0b1`
+// followed by `2`, while `0b1i` still carries its suffix. The prefix only
+// counts with a digit or `_` after it: `0or_else` is a `0` with an unknown
+// suffix, not an octal literal with a bad digit.
+fn input_to_number(input: &[u8]) -> LexResult<'_, i64> {
+    let prefix = terminated(
+        alt((tag_no_case("0b"), tag_no_case("0o"))),
+        peek(satisfy(|c| c.is_ascii_digit() || c == '_')),
+    );
+    let radix_digit = |b: u8| (b.is_ascii_alphanumeric() && b != b'i' && b != b'f') || b == b'_';
+    let radix_literal = tuple((opt(char('-')), prefix, take_while(radix_digit)));
+    let (i1, digits) = alt((recognize(radix_literal), recognize(pair(opt(char('-')), digit_groups))))(input)?;
+    let n = convert_slice_to_number(digits).map_err(|e| nom::Err::Failure(LexError::new(input, e)))?;
+    Ok((i1, n))
 }
 
-fn input_to_number(input: &[u8]) -> IResult<&[u8], i64> {
-    map_res(
-        recognize(
-            pair(
-                opt(char('-')),
-                many1(digit1),
-            )
-        ), |i| {
-            convert_slice_to_number(i)
-        })(input)
+fn lex_number(input: &[u8]) -> LexResult<'_, Token> {
+    let (i1, (digits, n)) = consumed(input_to_number)(input)?;
+    match numeric_suffix(i1)? {
+        (_, None) => Ok((i1, Token::NumericLiteral(n))),
+        (i2, Some(b"i")) => Ok((i2, Token::NumericLiteral(n))),
+        // The value is already parsed, and for `0b`/`0o` literals the text
+        // is no decimal. The sign is copied so that `-0f` stays -0.0.
+        (i2, Some(b"f")) => {
+            let sign = if digits.first() == Some(&b'-') { -1.0 } else { 1.0 };
+            Ok((i2, Token::DecimalLiteral((n as f64).copysign(sign))))
+        }
+        (_, Some(suffix)) => Err(suffix_failure(i1, suffix)),
+    }
 }
 
-fn lex_number(input: &[u8]) -> IResult<&[u8], Token> {
-    map(input_to_number, Token::NumericLiteral)(input)
+// Letters directly after a number form its type suffix: `i` for int, `f`
+// for decimal. Anything else is rejected rather than split off as an
+// identifier, so a typo like `10u` does not lex as `10 u`.
+fn numeric_suffix(input: &[u8]) -> LexResult<'_, Option<&[u8]>> {
+    opt(recognize(pair(alpha1, many0(alt((alphanumeric1, tag("_")))))))(input)
+}
+
+fn suffix_failure<'a>(input: &'a [u8], suffix: &[u8]) -> nom::Err<LexError<&'a [u8]>> {
+    let err = SuffixError::Unknown(String::from_utf8_lossy(suffix).into_owned());
+    nom::Err::Failure(LexError::new(input, err.into()))
 }
 
 // decimals
 
 fn convert_slice_to_decimal(s: &[u8]) -> Result<f64, ParseError> {
-    let r = convert_slice_to_utf8(s)?;
-    let f = str::parse::<f64>(r.as_str())?;
+    let text = str::from_utf8(s)?;
+    if !text.contains('_') {
+        return Ok(str::parse::<f64>(text)?);
+    }
+    check_separators(s, s)?;
+    let f = str::parse::<f64>(&text.replace('_', ""))?;
     Ok(f)
 }
 
-fn input_to_decimal(input: &[u8]) -> IResult<&[u8], f64> {
-    map_res(
+// `1.5`, or `1.` as long as the dot does not start a `..` range or a
+// member access like `1.abs`. Misplaced separators fail hard, like
+// integer overflow.
+fn input_to_decimal(input: &[u8]) -> LexResult<'_, f64> {
+    let (i1, literal) = alt((
         recognize(
             tuple((
-                pair(opt(char('-')), many1(digit1)),
+                pair(opt(char('-')), digit_groups),
                 char('.'),
-                many1(digit1),
+                digit_groups,
             ))
-        ), 
+        ),
+        recognize(
+            terminated(
+                tuple((opt(char('-')), digit_groups, char('.'))),
+                not(satisfy(|c| c == '.' || c == '_' || c.is_ascii_alphabetic())),
+            )
+        ),
+    ))(input)?;
+    let f = convert_slice_to_decimal(literal).map_err(|e| nom::Err::Failure(LexError::new(input, e)))?;
+    Ok((i1, f))
+}
+
+fn decimal_with_suffix(input: &[u8], f: f64) -> LexResult<'_, Token> {
+    match numeric_suffix(input)? {
+        (_, None) => Ok((input, Token::DecimalLiteral(f))),
+        (i1, Some(b"f")) => Ok((i1, Token::DecimalLiteral(f))),
+        (_, Some(b"i")) => Err(nom::Err::Failure(LexError::new(input, SuffixError::IntOnDecimal.into()))),
+        (_, Some(suffix)) => Err(suffix_failure(input, suffix)),
+    }
+}
+
+fn lex_decimal(input: &[u8]) -> LexResult<'_, Token> {
+    let (i1, f) = input_to_decimal(input)?;
+    decimal_with_suffix(i1, f)
+}
+
+// `.5`. Only tried where an operand may start, see lex_token_after.
+fn input_to_leading_dot_decimal(input: &[u8]) -> LexResult<'_, f64> {
+    map_res(
+        recognize(
+            tuple((opt(char('-')), char('.'), digit_groups))
+        ),
         |i| {
             convert_slice_to_decimal(i)
         })(input)
 }
 
-fn lex_decimal(input: &[u8]) -> IResult<&[u8], Token> {
-    map(input_to_decimal, Token::DecimalLiteral)(input)
+fn lex_leading_dot_decimal(input: &[u8]) -> LexResult<'_, Token> {
+    let (i1, f) = input_to_leading_dot_decimal(input)?;
+    decimal_with_suffix(i1, f)
+}
+
+// comments
+
+// Everything up to the line terminator, which is `\n` or `\r\n`, or up to
+// the end of the input. Token spans then never split a `\r\n` pair.
+fn rest_of_line(input: &[u8]) -> LexResult<'_, &[u8]> {
+    let (_, line) = take_till(|b| b == b'\n')(input)?;
+    let len = match line {
+        [.., b'\r'] if input.get(line.len()) == Some(&b'\n') => line.len() - 1,
+        _ => line.len(),
+    };
+    take(len)(input)
+}
+
+// A `//` comment runs up to the end of the line or of the input. Doc
+// comments are excluded, they become tokens.
+fn line_comment(input: &[u8]) -> LexResult<'_, &[u8]> {
+    recognize(tuple((not(doc_comment_marker), tag("//"), rest_of_line)))(input)
+}
+
+// Exactly three slashes, so a `////` rule line stays a plain comment.
+fn doc_comment_marker(input: &[u8]) -> LexResult<'_, &[u8]> {
+    terminated(tag("///"), not(char('/')))(input)
+}
+
+// Text of one `///` line, without the marker, one leading space and a
+// trailing carriage return.
+fn doc_comment_line(input: &[u8]) -> LexResult<'_, String> {
+    let (rest, line) = preceded(doc_comment_marker, rest_of_line)(input)?;
+    let line = line.strip_prefix(b" ").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok((rest, String::from_utf8_lossy(line).into_owned()))
+}
+
+// Consecutive `///` lines make up one DocComment, joined with newlines.
+// A blank line or any other token in between starts a new one.
+fn lex_doc_comment(input: &[u8]) -> LexResult<'_, Token> {
+    let (mut rest, first) = doc_comment_line(input)?;
+    let mut lines = vec![first];
+    while let Ok((i1, line)) = preceded(tuple((space0, line_ending, space0)), doc_comment_line)(rest) {
+        lines.push(line);
+        rest = i1;
+    }
+    Ok((rest, Token::DocComment(lines.join("\n"))))
+}
+
+// A `/* ... */` comment, which may span lines and nests like Rust's, so
+// commenting out code that already has block comments works. Without
+// enough closing `*/` it fails at the outermost opening delimiter instead
+// of swallowing the rest of the file.
+fn block_comment(input: &[u8]) -> LexResult<'_, &[u8]> {
+    let (mut rest, _) = tag("/*")(input)?;
+    let mut depth = 1usize;
+    loop {
+        match rest {
+            [b'*', b'/', tail @ ..] => {
+                depth -= 1;
+                rest = tail;
+                if depth == 0 {
+                    let len = input.len() - tail.len();
+                    return Ok((tail, input.get(..len).unwrap_or_default()))
+                }
+            }
+            [b'/', b'*', tail @ ..] => {
+                depth += 1;
+                rest = tail;
+            }
+            [_, tail @ ..] => rest = tail,
+            [] => return Err(nom::Err::Failure(LexError::new(input, UnterminatedError::new("block comment", "*/").into()))),
+        }
+    }
+}
+
+// Whitespace and comments between tokens, all discarded.
+fn trivia(input: &[u8]) -> LexResult<'_, ()> {
+    let mut rest = input;
+    loop {
+        let (i1, _) = multispace0(rest)?;
+        match alt((line_comment, block_comment))(i1) {
+            Ok((i2, _)) => rest = i2,
+            Err(nom::Err::Error(_)) => return Ok((i1, ())),
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 // meta
 
-fn lex_illegal(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_illegal(input: &[u8]) -> LexResult<'_, Token> {
     map(take(1usize), |_| Token::Illegal)(input)
 }
 
 // concrete lexer
 
-fn lex_token(input: &[u8]) -> IResult<&[u8], Token> {
+fn lex_token(input: &[u8]) -> LexResult<'_, Token> {
     alt((
+        lex_doc_comment,
         lex_decimal,
         lex_number,
         lex_punctuation,
@@ -313,22 +765,244 @@ fn lex_token(input: &[u8]) -> IResult<&[u8], Token> {
         lex_operator,
         lex_char,
         lex_ident_or_reserved,
+        lex_triple_quoted_string,
         lex_string,
         lex_illegal,
     ))(input)
 }
 
-fn lex_tokens(input: &[u8]) -> IResult<&[u8], Vec<Token>> {
-    many0(delimited(multispace0, lex_token, multispace0))(input)
+// Tokens after which a dot reads as member access (`x.5`, `f().0`) or as
+// part of a range (`1..5`), never as the start of a decimal. Illegal is
+// included so the second dot of `..` is not taken either.
+fn ends_operand(token: &Token) -> bool {
+    matches!(token,
+        Token::Ident(_)
+        | Token::StringLiteral(_)
+        | Token::InterpolatedString(_)
+        | Token::CharLiteral(_)
+        | Token::NumericLiteral(_)
+        | Token::DecimalLiteral(_)
+        | Token::BoolLiteral(_)
+        | Token::RParenthesis
+        | Token::RBracket
+        | Token::RBrace
+        | Token::Illegal
+    )
+}
+
+// lex_token, plus the leading-dot decimal form when the previous token
+// cannot be followed by a member access.
+fn lex_token_after<'a>(prev: Option<&Token>, input: &'a [u8]) -> LexResult<'a, Token> {
+    match prev {
+        Some(token) if ends_operand(token) => lex_token(input),
+        _ => alt((lex_leading_dot_decimal, lex_token))(input),
+    }
+}
+
+fn lex_tokens(input: &[u8]) -> LexResult<'_, Vec<Token>> {
+    map(lex_spanned_tokens, |tokens| tokens.into_iter().map(|(token, _)| token).collect())(input)
+}
+
+fn lex_spanned_tokens(input: &[u8]) -> LexResult<'_, Vec<SpannedToken>> {
+    lex_spanned_tokens_recovering(input, None)
+}
+
+// With an error sink, a hard error does not abort lexing. Everything from
+// the failed token up to the end of the line becomes one Illegal token and
+// lexing resumes on the next line.
+fn lex_spanned_tokens_recovering<'a>(input: &'a [u8], mut errors: Option<&mut Vec<LexError<&'a [u8]>>>) -> LexResult<'a, Vec<SpannedToken>> {
+    let offset = |rest: &[u8]| input.len() - rest.len();
+    let mut tokens = vec![];
+    let mut rest = input;
+    loop {
+        let i1 = match (trivia(rest), errors.as_mut()) {
+            (Ok((i1, _)), _) => i1,
+            // An unterminated block comment runs to the end of the input,
+            // so there is nothing left to resume at.
+            (Err(nom::Err::Failure(e)), Some(errors)) => {
+                tokens.push((Token::Illegal, Span::new(offset(e.input), input.len())));
+                errors.push(e);
+                return Ok((&[], tokens));
+            }
+            (Err(e), _) => return Err(e),
+        };
+        match (lex_token_after(tokens.last().map(|(token, _)| token), i1), errors.as_mut()) {
+            (Ok((i2, token)), _) => {
+                tokens.push((token, Span::new(offset(i1), offset(i2))));
+                rest = i2;
+            }
+            (Err(nom::Err::Error(_)), _) => return Ok((i1, tokens)),
+            (Err(nom::Err::Failure(e)), Some(errors)) => {
+                rest = rest_of_line(e.input).map_or(e.input, |(i2, _)| i2);
+                tokens.push((Token::Illegal, Span::new(offset(i1), offset(rest))));
+                errors.push(e);
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+}
+
+// Knobs for Lexer::lex_with_options. Everything is off by default, so the
+// result matches lexer_spanned_tokens.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LexerOptions {
+    pub warn_missing_final_newline: bool,
+    // Lex RESERVED_WORDS as plain identifiers, for code written before
+    // they were reserved.
+    pub reserved_words_as_idents: bool,
+    pub keywords: KeywordTable,
+}
+
+// Re-resolves word tokens against a custom keyword table. The default
+// table is built into the rules, so this only rewrites what differs.
+fn apply_keywords(token: &mut Token, table: &KeywordTable) {
+    match token {
+        Token::Ident(word) | Token::ReservedWord(word) => *token = table.lookup(mem::take(word)),
+        Token::InterpolatedString(parts) => {
+            for part in parts.iter_mut() {
+                if let StringPart::Expr(tokens) = part {
+                    tokens.iter_mut().for_each(|token| apply_keywords(token, table));
+                }
+            }
+        }
+        _ => {
+            if let Some((word, _)) = KEYWORDS.iter().find(|(_, keyword)| keyword == token) {
+                *token = table.lookup((*word).to_owned());
+            }
+        }
+    }
+}
+
+// Non-fatal findings about the input, for tools like the formatter to act on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LexWarning {
+    // Zero-width span at the end of a non-empty input not ending in '\n'.
+    MissingFinalNewline(Span),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct LexOutput {
+    pub tokens: Vec<SpannedToken>,
+    pub warnings: Vec<LexWarning>,
+}
+
+// Which token the lexer produced at an offset, and which shorter symbols
+// would also have matched where it starts.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Explanation {
+    pub matched: SpannedToken,
+    // Longest first.
+    pub alternatives: Vec<SpannedToken>,
+}
+
+fn explain((token, span): SpannedToken) -> Explanation {
+    let alternatives = rejected_candidate(&token)
+        .map(|candidate| {
+            let len = candidate.to_string().len();
+            (candidate, Span::new(span.start, span.start + len))
+        })
+        .into_iter()
+        .collect();
+    Explanation { matched: (token, span), alternatives }
+}
+
+// Throughput of a single Lexer::lex_timed run.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LexReport {
+    // Including the trailing EOF.
+    pub tokens: usize,
+    pub bytes: usize,
+    pub duration: Duration,
+}
+
+impl LexReport {
+    pub fn tokens_per_second(&self) -> f64 {
+        self.tokens as f64 / self.duration.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
 }
 
 pub struct Lexer;
 
 impl Lexer {
-    pub fn lexer_tokens(bytes: &[u8]) -> IResult<&[u8], Vec<Token>> {
+    pub fn lexer_tokens(bytes: &[u8]) -> IResult<&[u8], Vec<Token>, LexError<&[u8]>> {
         lex_tokens(bytes)
             .map(|(slice, result)| (slice, [&result[..], &vec![Token::EOF][..]].concat()))
     }
+
+    // Same tokens as lexer_tokens, each paired with its byte range in the input.
+    // EOF gets a zero-width span at the end of the input.
+    pub fn lexer_spanned_tokens(bytes: &[u8]) -> IResult<&[u8], Vec<SpannedToken>, LexError<&[u8]>> {
+        lex_spanned_tokens(bytes).map(|(slice, mut result)| {
+            result.push((Token::EOF, Span::new(bytes.len(), bytes.len())));
+            (slice, result)
+        })
+    }
+
+    // Never fails: hard errors are collected instead, each leaving an
+    // Illegal token in the stream, so tools get tokens for the rest of
+    // the input.
+    pub fn lex_with_recovery(bytes: &[u8]) -> (Vec<SpannedToken>, Vec<LexError<&[u8]>>) {
+        let mut errors = vec![];
+        let mut tokens = match lex_spanned_tokens_recovering(bytes, Some(&mut errors)) {
+            Ok((_, tokens)) => tokens,
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                errors.push(e);
+                vec![]
+            }
+            Err(nom::Err::Incomplete(_)) => vec![],
+        };
+        tokens.push((Token::EOF, Span::new(bytes.len(), bytes.len())));
+        (tokens, errors)
+    }
+
+    // Explains the token covering `offset`, None past the last token.
+    pub fn explain_at(bytes: &[u8], offset: usize) -> Option<Explanation> {
+        let (tokens, _) = Lexer::lex_with_recovery(bytes);
+        tokens.into_iter()
+            .find(|(_, span)| span.start <= offset && offset < span.end)
+            .map(explain)
+    }
+
+    // Every operator where longest-match won over a shorter symbol and an
+    // adjacent byte could have formed another operator, like the `>>` of
+    // `x>>=2`. For tooling that wants to annotate dense operator sequences.
+    pub fn operator_ambiguities(bytes: &[u8]) -> Vec<Explanation> {
+        let (tokens, _) = Lexer::lex_with_recovery(bytes);
+        tokens.into_iter()
+            .filter(|(_, span)| contested(bytes, *span))
+            .map(explain)
+            .filter(|explanation| !explanation.alternatives.is_empty())
+            .collect()
+    }
+
+    // lexer_tokens, plus how long it took. The type is spelled out like the
+    // other entry points, as LexResult is private.
+    #[allow(clippy::type_complexity)]
+    pub fn lex_timed(bytes: &[u8]) -> IResult<&[u8], (Vec<Token>, LexReport), LexError<&[u8]>> {
+        let start = Instant::now();
+        let (slice, tokens) = Lexer::lexer_tokens(bytes)?;
+        let report = LexReport { tokens: tokens.len(), bytes: bytes.len(), duration: start.elapsed() };
+        Ok((slice, (tokens, report)))
+    }
+
+    pub fn lex_with_options(bytes: &[u8], options: LexerOptions) -> IResult<&[u8], LexOutput, LexError<&[u8]>> {
+        let (slice, mut tokens) = Lexer::lexer_spanned_tokens(bytes)?;
+        if options.keywords != KeywordTable::default() {
+            tokens.iter_mut().for_each(|(token, _)| apply_keywords(token, &options.keywords));
+        }
+        if options.reserved_words_as_idents {
+            for (token, _) in tokens.iter_mut() {
+                if let Token::ReservedWord(word) = token {
+                    *token = Token::Ident(mem::take(word));
+                }
+            }
+        }
+        let mut warnings = vec![];
+        if options.warn_missing_final_newline && bytes.last().is_some_and(|b| *b != b'\n') {
+            warnings.push(LexWarning::MissingFinalNewline(Span::new(bytes.len(), bytes.len())));
+        }
+        Ok((slice, LexOutput { tokens, warnings }))
+    }
 }
 
 // tests
@@ -363,7 +1037,611 @@ mod tests {
     }
 
     fn str_to_u8_slice(s: &str) -> &[u8] {
-        &s.as_bytes()[..]
+        s.as_bytes()
+    }
+
+    fn lex_failure(input: &[u8]) -> LexError<&[u8]> {
+        match Lexer::lexer_tokens(input) {
+            Err(nom::Err::Failure(e)) => e,
+            other => panic!("expected a lexer failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_spanned_tokens() {
+        let input = str_to_u8_slice("let  s = \"a ${b}\";\n'❤' 1.5");
+        let (_, result) = Lexer::lexer_spanned_tokens(input).unwrap();
+        let spans = result.iter().map(|(_, span)| (span.start, span.end)).collect::<Vec<_>>();
+        assert_eq!(spans, vec![(0, 3), (5, 6), (7, 8), (9, 17), (17, 18), (19, 24), (25, 28), (28, 28)]);
+        let tokens = result.into_iter().map(|(token, _)| token).collect::<Vec<_>>();
+        assert_eq!(tokens, Lexer::lexer_tokens(input).unwrap().1);
+    }
+
+    fn eof_span(input: &str) -> Span {
+        let (_, result) = Lexer::lexer_spanned_tokens(str_to_u8_slice(input)).unwrap();
+        match result.last() {
+            Some((Token::EOF, span)) => *span,
+            other => panic!("expected trailing EOF, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eof_span() {
+        assert_eq!(eof_span(""), Span::new(0, 0));
+        assert_eq!(eof_span("let a = 5"), Span::new(9, 9));
+        assert_eq!(eof_span("a;"), Span::new(2, 2));
+        assert_eq!(eof_span("a;  \n\t"), Span::new(6, 6));
+        assert_eq!(eof_span("\"unterminated"), Span::new(13, 13));
+        assert_eq!(eof_span("a; // trailing"), Span::new(14, 14));
+    }
+
+    fn newline_warnings(input: &str) -> Vec<LexWarning> {
+        let options = LexerOptions { warn_missing_final_newline: true, ..LexerOptions::default() };
+        let (_, output) = Lexer::lex_with_options(str_to_u8_slice(input), options).unwrap();
+        output.warnings
+    }
+
+    #[test]
+    fn test_missing_final_newline() {
+        assert_eq!(newline_warnings("let a = 5;"), vec![LexWarning::MissingFinalNewline(Span::new(10, 10))]);
+        assert_eq!(newline_warnings("let a = 5;\n"), vec![]);
+        assert_eq!(newline_warnings("let a = 5;\r\n"), vec![]);
+        assert_eq!(newline_warnings(""), vec![]);
+        let (_, output) = Lexer::lex_with_options(str_to_u8_slice("a"), LexerOptions::default()).unwrap();
+        assert!(output.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_reserved_words() {
+        let input = str_to_u8_slice("let match = 1; struct_ matches");
+        let (_, result) = Lexer::lexer_tokens(input).unwrap();
+        assert_eq!(result, vec![
+            Token::Let,
+            Token::ReservedWord(String::from("match")),
+            Token::Assign,
+            Token::NumericLiteral(1),
+            Token::Semicolon,
+            token_ident! {"struct_"},
+            token_ident! {"matches"},
+            Token::EOF,
+        ]);
+        for word in RESERVED_WORDS {
+            let (_, result) = Lexer::lexer_tokens(word.as_bytes()).unwrap();
+            assert_eq!(result, vec![Token::ReservedWord(String::from(word)), Token::EOF]);
+        }
+    }
+
+    #[test]
+    fn test_contextual_keywords() {
+        let input = str_to_u8_slice("let in = 5; for x in xs { x as int }");
+        let (_, result) = Lexer::lexer_tokens(input).unwrap();
+        assert_eq!(result, vec![
+            Token::Let,
+            token_ident! {"in"},
+            Token::Assign,
+            Token::NumericLiteral(5),
+            Token::Semicolon,
+            Token::ReservedWord(String::from("for")),
+            token_ident! {"x"},
+            token_ident! {"in"},
+            token_ident! {"xs"},
+            Token::LBrace,
+            token_ident! {"x"},
+            token_ident! {"as"},
+            token_ident! {"int"},
+            Token::RBrace,
+            Token::EOF,
+        ]);
+        assert!(result[1].is_contextual("in") && result[7].is_contextual("in"));
+        assert!(result[11].is_contextual("as"));
+    }
+
+    #[test]
+    fn test_reserved_words_as_idents() {
+        let options = LexerOptions { reserved_words_as_idents: true, ..LexerOptions::default() };
+        let (_, output) = Lexer::lex_with_options(str_to_u8_slice("let type = 1;"), options).unwrap();
+        let tokens = output.tokens.into_iter().map(|(token, _)| token).collect::<Vec<_>>();
+        assert_eq!(tokens, vec![
+            Token::Let,
+            token_ident! {"type"},
+            Token::Assign,
+            Token::NumericLiteral(1),
+            Token::Semicolon,
+            Token::EOF,
+        ]);
+    }
+
+    #[test]
+    fn test_lex_with_recovery() {
+        let input = str_to_u8_slice("let s = \"bad \\xff\" + 1;\nfn after() {}\nlet t = \"${\n");
+        let (result, errors) = Lexer::lex_with_recovery(input);
+        let tokens = result.iter().map(|(token, _)| token.clone()).collect::<Vec<_>>();
+        assert_eq!(tokens, vec![
+            Token::Let,
+            token_ident! {"s"},
+            Token::Assign,
+            Token::Illegal,
+            Token::Function,
+            token_ident! {"after"},
+            Token::LParenthesis,
+            Token::RParenthesis,
+            Token::LBrace,
+            Token::RBrace,
+            Token::Let,
+            token_ident! {"t"},
+            Token::Assign,
+            Token::Illegal,
+            Token::EOF,
+        ]);
+        assert_eq!(result[3].1, Span::new(8, 23));
+        assert_eq!(result[13].1, Span::new(46, 49));
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].kind, LexErrorKind::Parse(ParseError::InvalidEscape(EscapeError::HexOutOfRange(0xff)))));
+        assert!(matches!(errors[1].kind, LexErrorKind::Parse(ParseError::Unterminated(_))));
+    }
+
+    #[test]
+    fn test_lex_with_recovery_matches_clean_input() {
+        let input = str_to_u8_slice("let a = \"x ${b}\";\n1.5");
+        let (result, errors) = Lexer::lex_with_recovery(input);
+        assert!(errors.is_empty());
+        assert_eq!(result, Lexer::lexer_spanned_tokens(input).unwrap().1);
+    }
+
+    #[test]
+    fn test_lex_timed() {
+        let input = str_to_u8_slice("let a = 1;");
+        let (_, (tokens, report)) = Lexer::lex_timed(input).unwrap();
+        assert_eq!(tokens, Lexer::lexer_tokens(input).unwrap().1);
+        assert_eq!(report.tokens, 6);
+        assert_eq!(report.bytes, 10);
+        assert!(report.tokens_per_second() > 0.0);
+    }
+
+    // Guards against rules that slow down every token. Skipped unless
+    // KALEIDO_LEX_TOKENS_PER_SEC_FLOOR is set, since the rate depends on
+    // the machine and the build profile.
+    #[test]
+    fn test_lex_rate_floor() {
+        let Some(floor) = std::env::var("KALEIDO_LEX_TOKENS_PER_SEC_FLOOR").ok().and_then(|v| v.parse::<f64>().ok()) else {
+            return;
+        };
+        let source = crate::testgen::generate(crate::testgen::GenOptions { seed: 1, functions: 1000, statements: 100, max_depth: 3 });
+        let (_, (_, report)) = Lexer::lex_timed(source.as_bytes()).unwrap();
+        assert!(report.tokens_per_second() >= floor, "lexed {:.0} tokens/s, floor is {:.0}: {:?}", report.tokens_per_second(), floor, report);
+    }
+
+    #[test]
+    fn test_control_escapes() {
+        let (_, result) = Lexer::lexer_tokens(str_to_u8_slice(r#""a\nb" "\t\r\0" '\n' '\0'"#)).unwrap();
+        assert_eq!(result, vec![
+            Token::StringLiteral(String::from("a\nb")),
+            Token::StringLiteral(String::from("\t\r\0")),
+            Token::CharLiteral('\n'),
+            Token::CharLiteral('\0'),
+            Token::EOF,
+        ]);
+        let Token::StringLiteral(s) = &result[0] else { unreachable!() };
+        assert_eq!(s.chars().collect::<Vec<_>>(), vec!['a', '\n', 'b']);
+    }
+
+    #[test]
+    fn test_spans_never_split_crlf() {
+        let mut source = crate::testgen::generate(crate::testgen::GenOptions { seed: 7, functions: 3, ..Default::default() });
+        source.push_str("/// doc\n/// more\nlet s = \"bad \\q escape\";\nlet t = 1; // trailing\n");
+        let source = source.replace('\n', "\r\n");
+        let bytes = source.as_bytes();
+        let (tokens, errors) = Lexer::lex_with_recovery(bytes);
+        assert_eq!(errors.len(), 1);
+        assert!(tokens.contains(&(Token::DocComment(String::from("doc\nmore")), Span::new(source.find("///").unwrap(), source.find("more").unwrap() + 4))));
+        let splits = |offset: usize| offset > 0 && bytes.get(offset - 1) == Some(&b'\r') && bytes.get(offset) == Some(&b'\n');
+        for (token, span) in &tokens {
+            assert!(!splits(span.start) && !splits(span.end), "{:?} at {:?} splits a CRLF", token, span);
+        }
+    }
+
+    #[test]
+    fn test_unknown_escape_is_one_error() {
+        let input = str_to_u8_slice("let s = \"ab\\qcd\";\nlet t = 1;");
+        let (result, errors) = Lexer::lex_with_recovery(input);
+        assert_eq!(errors.len(), 1);
+        let located = LocatedLexError::from_nom(input, nom::Err::Failure(errors.into_iter().next().unwrap()));
+        assert_eq!(located.offset, 11);
+        assert_eq!(located.span(), Span::new(11, 13));
+        assert!(!located.recoverable);
+        assert!(matches!(located.kind, LexErrorKind::Parse(ParseError::InvalidEscape(EscapeError::Unknown('q')))));
+        assert_eq!(located.to_string(), "Unknown escape sequence \\q at byte 11");
+        let illegal = result.iter().filter(|(token, _)| *token == Token::Illegal).count();
+        assert_eq!(illegal, 1);
+    }
+
+    #[test]
+    fn test_unknown_escape_names_the_character() {
+        for (source, message) in [
+            ("\"\\é\"", "Unknown escape sequence \\é"),
+            ("\"\\❤\"", "Unknown escape sequence \\❤"),
+            ("'\\🦀'", "Unknown escape sequence \\🦀"),
+        ] {
+            let err = lex_failure(str_to_u8_slice(source));
+            assert_eq!(err.to_string(), message, "{}", source);
+            assert_eq!(err.len, source.len() - 2, "{}", source);
+        }
+        let input = b"\"\\\xff\"";
+        assert!(matches!(lex_failure(input).kind, LexErrorKind::Parse(ParseError::InvalidEscape(EscapeError::Unknown(char::REPLACEMENT_CHARACTER)))));
+    }
+
+    #[test]
+    fn test_escape_error_spans() {
+        let padding = "x".repeat(50);
+        for (escape, error) in [
+            (r"\u{}", "Malformed unicode escape"),
+            (r"\u{d800}", "is not a character"),
+            (r"\u{12", "Malformed unicode escape"),
+            (r"\xff", "is out of range"),
+            (r"\x4", "exactly two hex digits"),
+            (r"\x", "exactly two hex digits"),
+            (r"\é", "Unknown escape sequence"),
+        ] {
+            let source = format!("let s = \"{}{}{}\";", padding, escape, padding);
+            let input = source.as_bytes();
+            let located = LocatedLexError::from_nom(input, Lexer::lexer_tokens(input).unwrap_err());
+            let start = 9 + padding.len();
+            assert_eq!(located.span(), Span::new(start, start + escape.len()), "{}", escape);
+            assert!(located.to_string().contains(error), "{}: {}", escape, located);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_block_comment_recovery() {
+        let input = str_to_u8_slice("let a = 1; /* open\nlet b = 2;");
+        let (result, errors) = Lexer::lex_with_recovery(input);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(result[result.len() - 2], (Token::Illegal, Span::new(11, input.len())));
+        assert_eq!(result.last(), Some(&(Token::EOF, Span::new(input.len(), input.len()))));
+    }
+
+    check_tokens! {test_binary_literals, "0b0 0b11111111 0B101 -0b1 0b0111111111111111111111111111111111111111111111111111111111111111", vec![
+        Token::NumericLiteral(0),
+        Token::NumericLiteral(255),
+        Token::NumericLiteral(5),
+        Token::NumericLiteral(-1),
+        Token::NumericLiteral(i64::MAX),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_invalid_binary_literals() {
+        for (source, error) in [
+            ("let a = 0b2;", NumberError::InvalidDigit { digit: '2', radix: 2 }),
+            ("let a = 0b1012;", NumberError::InvalidDigit { digit: '2', radix: 2 }),
+            ("let a = 0b1z;", NumberError::InvalidDigit { digit: 'z', radix: 2 }),
+        ] {
+            let input = str_to_u8_slice(source);
+            let err = lex_failure(input);
+            assert_eq!(err.input, &input[8..]);
+            assert_eq!(err.to_string(), error.to_string());
+        }
+        assert_eq!(lex_failure(str_to_u8_slice("0b2")).to_string(), "Digit '2' is not valid in a base 2 literal");
+    }
+
+    // Without a digit after it, `0b` or `0o` is a `0` and the start of a suffix.
+    #[test]
+    fn test_radix_prefix_needs_a_digit() {
+        for (source, suffix) in [("0or_else", "or_else"), ("0bad", "bad"), ("let a = 0o;", "o"), ("-0B", "B")] {
+            let err = lex_failure(str_to_u8_slice(source));
+            assert_eq!(err.to_string(), format!("Unknown numeric suffix `{}`, expected `i` or `f`", suffix));
+        }
+    }
+
+    check_tokens! {test_octal_literals, "0o0 0o777 0O755 -0o10 0o777777777777777777777", vec![
+        Token::NumericLiteral(0),
+        Token::NumericLiteral(511),
+        Token::NumericLiteral(493),
+        Token::NumericLiteral(-8),
+        Token::NumericLiteral(i64::MAX),
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_radix_literal_suffixes, "0b1i 0o7i -0b101i 0b1f 0b101f -0o17f -0f 0f", vec![
+        Token::NumericLiteral(1),
+        Token::NumericLiteral(7),
+        Token::NumericLiteral(-5),
+        Token::DecimalLiteral(1.0),
+        Token::DecimalLiteral(5.0),
+        Token::DecimalLiteral(-15.0),
+        Token::DecimalLiteral(-0.0),
+        Token::DecimalLiteral(0.0),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_radix_literal_unknown_suffix() {
+        let err = lex_failure(str_to_u8_slice("0b1iz"));
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidSuffix(SuffixError::Unknown(ref s))) if s == "iz"));
+    }
+
+    #[test]
+    fn test_invalid_octal_literals() {
+        for (source, error) in [
+            ("let a = 0o8;", NumberError::InvalidDigit { digit: '8', radix: 8 }),
+            ("let a = 0o1239;", NumberError::InvalidDigit { digit: '9', radix: 8 }),
+        ] {
+            let input = str_to_u8_slice(source);
+            let err = lex_failure(input);
+            assert_eq!(err.input, &input[8..]);
+            assert_eq!(err.to_string(), error.to_string());
+        }
+        assert_eq!(lex_failure(str_to_u8_slice("0o8")).to_string(), "Digit '8' is not valid in a base 8 literal");
+        let overflow = lex_failure(str_to_u8_slice("0o1000000000000000000000"));
+        assert_eq!(overflow.to_string(), "Integer literal 0o1000000000000000000000 does not fit in 64 bits");
+    }
+
+    check_tokens! {test_digit_separators, "1_000_000_000 -1_000 3_141.592_653 -0.000_1 1_000. 0b1010_1010 0o7_5_5 1_0f _100abc", vec![
+        Token::NumericLiteral(1_000_000_000),
+        Token::NumericLiteral(-1_000),
+        Token::DecimalLiteral(3_141.592_653),
+        Token::DecimalLiteral(-0.000_1),
+        Token::DecimalLiteral(1_000.0),
+        Token::NumericLiteral(0b1010_1010),
+        Token::NumericLiteral(0o755),
+        Token::DecimalLiteral(10.0),
+        token_ident! {"_100abc"},
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_misplaced_digit_separators() {
+        for literal in ["100_", "1__0", "-1_", "1_.5", "1.5_", "1.5__0", "0b_1", "0o7_", "1_000_"] {
+            let source = format!("let a = {};", literal);
+            let input = source.as_bytes();
+            let err = lex_failure(input);
+            assert_eq!(err.input, &input[8..], "{}", literal);
+            assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidNumber(NumberError::MisplacedSeparator(_)))), "{}: {}", literal, err);
+        }
+        assert_eq!(lex_failure(str_to_u8_slice("1__0")).to_string(), "Digit separators in 1__0 must sit between two digits");
+        for literal in ["0B_", "0b1_", "-0o_7"] {
+            let message = format!("Digit separators in {} must sit between two digits", literal);
+            assert_eq!(lex_failure(str_to_u8_slice(literal)).to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_number_overflow_is_failure() {
+        let input = str_to_u8_slice("let a = 99999999999999999999;");
+        let located = LocatedLexError::from_nom(input, Lexer::lexer_tokens(input).unwrap_err());
+        assert_eq!(located.offset, 8);
+        assert!(!located.recoverable);
+        assert_eq!(located.to_string(), "Integer literal 99999999999999999999 does not fit in 64 bits at byte 8");
+    }
+
+    #[test]
+    fn test_error_offset_outside_source() {
+        let err = LexError::new(str_to_u8_slice("elsewhere"), EscapeError::TruncatedHex.into());
+        assert_eq!(err.offset_in(str_to_u8_slice("source")), None);
+        let source = str_to_u8_slice("source");
+        assert_eq!(LexError::new(&source[6..], EscapeError::TruncatedHex.into()).offset_in(source), Some(6));
+    }
+
+    // Every fixed-spelling symbol the rules produce.
+    const SYMBOLS: [Token; 31] = [
+        Token::Equal, Token::NotEqual, Token::Exp, Token::Plus, Token::Modulo, Token::Minus,
+        Token::Mult, Token::Div, Token::Not, Token::GreaterThanEqual, Token::LessThanEqual,
+        Token::GreaterThan, Token::LessThan, Token::Assign, Token::FunctionReturn,
+        Token::Semicolon, Token::Colon, Token::Comma, Token::LParenthesis, Token::RParenthesis,
+        Token::LBrace, Token::RBrace, Token::LBracket, Token::RBracket,
+        Token::BooleanAnd, Token::BooleanOr, Token::BooleanXor, Token::LShift, Token::RShift,
+        Token::LogicAnd, Token::LogicOr,
+    ];
+
+    // Each multi-character symbol records exactly the shorter symbol its
+    // spelling starts with, and single-character ones record nothing.
+    #[test]
+    fn test_rejected_candidates_match_spellings() {
+        for symbol in SYMBOLS {
+            let spelling = symbol.to_string();
+            let prefixes = SYMBOLS.iter()
+                .filter(|other| other.to_string().len() < spelling.len() && spelling.starts_with(&other.to_string()))
+                .cloned()
+                .collect::<Vec<_>>();
+            assert_eq!(rejected_candidate(&symbol).into_iter().collect::<Vec<_>>(), prefixes, "{}", spelling);
+        }
+    }
+
+    #[test]
+    fn test_symbols_lex_to_themselves() {
+        for symbol in SYMBOLS {
+            let (_, result) = Lexer::lexer_tokens(symbol.to_string().as_bytes()).unwrap();
+            assert_eq!(result, vec![symbol.clone(), Token::EOF]);
+        }
+    }
+
+    #[test]
+    fn test_explain_at() {
+        let explanation = Lexer::explain_at(str_to_u8_slice("a<<=1"), 2).unwrap();
+        assert_eq!(explanation.matched, (Token::LShift, Span::new(1, 3)));
+        assert_eq!(explanation.alternatives, vec![(Token::LessThan, Span::new(1, 2))]);
+
+        let explanation = Lexer::explain_at(str_to_u8_slice("x>>=2"), 1).unwrap();
+        assert_eq!(explanation.matched, (Token::RShift, Span::new(1, 3)));
+        assert_eq!(explanation.alternatives, vec![(Token::GreaterThan, Span::new(1, 2))]);
+        let explanation = Lexer::explain_at(str_to_u8_slice("x>>=2"), 3).unwrap();
+        assert_eq!(explanation.matched, (Token::Assign, Span::new(3, 4)));
+        assert!(explanation.alternatives.is_empty());
+
+        let explanation = Lexer::explain_at(str_to_u8_slice("p<=>q"), 1).unwrap();
+        assert_eq!(explanation.matched, (Token::LessThanEqual, Span::new(1, 3)));
+        assert_eq!(explanation.alternatives, vec![(Token::LessThan, Span::new(1, 2))]);
+        let explanation = Lexer::explain_at(str_to_u8_slice("p<=>q"), 3).unwrap();
+        assert_eq!(explanation.matched, (Token::GreaterThan, Span::new(3, 4)));
+        assert!(explanation.alternatives.is_empty());
+
+        assert!(Lexer::explain_at(str_to_u8_slice("p "), 1).is_none());
+    }
+
+    #[test]
+    fn test_operator_ambiguities() {
+        let found = Lexer::operator_ambiguities(str_to_u8_slice("a<<=b>>=c && x=**y a!==b p&&&q"));
+        let matched = found.into_iter().map(|explanation| explanation.matched.0).collect::<Vec<_>>();
+        assert_eq!(matched, vec![Token::LShift, Token::RShift, Token::NotEqual, Token::LogicAnd]);
+    }
+
+    // Operators with only one reading are not reported, however many
+    // characters they have.
+    #[test]
+    fn test_unambiguous_operators_are_not_reported() {
+        for source in ["x /* a */ != y", "a == b && c || d", "fn f() -> int {}", "a <= b >= c", "x = 2 ** 3", "p<=>q"] {
+            assert!(Lexer::operator_ambiguities(str_to_u8_slice(source)).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_non_operators_are_not_ambiguities() {
+        assert!(Lexer::operator_ambiguities(str_to_u8_slice("/// doc\nfn f() {}")).is_empty());
+        assert!(Lexer::operator_ambiguities(str_to_u8_slice("let a = -1.5; let b = -2; c(-.5)")).is_empty());
+        let explanation = Lexer::explain_at(str_to_u8_slice("let a = -1.5;"), 8).unwrap();
+        assert_eq!(explanation.matched, (Token::DecimalLiteral(-1.5), Span::new(8, 12)));
+        assert!(explanation.alternatives.is_empty());
+    }
+
+    fn lex_with_keywords(input: &str, keywords: KeywordTable) -> Vec<Token> {
+        let options = LexerOptions { keywords, ..LexerOptions::default() };
+        let (_, output) = Lexer::lex_with_options(str_to_u8_slice(input), options).unwrap();
+        output.tokens.into_iter().map(|(token, _)| token).collect()
+    }
+
+    #[test]
+    fn test_custom_keywords() {
+        let mut keywords = KeywordTable::default();
+        keywords.add("when", Token::Custom(1)).remove("while");
+        let result = lex_with_keywords("when x { while; let s = \"${when}\"; }", keywords);
+        assert_eq!(result, vec![
+            Token::Custom(1),
+            token_ident! {"x"},
+            Token::LBrace,
+            token_ident! {"while"},
+            Token::Semicolon,
+            Token::Let,
+            token_ident! {"s"},
+            Token::Assign,
+            Token::InterpolatedString(vec![StringPart::Expr(vec![Token::Custom(1)])]),
+            Token::Semicolon,
+            Token::RBrace,
+            Token::EOF,
+        ]);
+    }
+
+    #[test]
+    fn test_default_keyword_table_matches_rules() {
+        for (word, token) in KEYWORDS {
+            assert_eq!(Lexer::lexer_tokens(word.as_bytes()).unwrap().1, vec![token.clone(), Token::EOF]);
+            assert_eq!(KeywordTable::default().get(word), Some(&token));
+        }
+        let result = lex_with_keywords("fn when() -> match", KeywordTable::default());
+        assert_eq!(result, Lexer::lexer_tokens(str_to_u8_slice("fn when() -> match")).unwrap().1);
+    }
+
+    #[test]
+    fn test_line_comment() {
+        let commented = Lexer::lexer_tokens(str_to_u8_slice("let a = 5; // set a\n// whole line\nlet b = a;")).unwrap().1;
+        let plain = Lexer::lexer_tokens(str_to_u8_slice("let a = 5;\nlet b = a;")).unwrap().1;
+        assert_eq!(commented, plain);
+    }
+
+    check_tokens! {test_line_comment_at_end_of_input, "a / b // no newline after this", vec![
+        token_ident! {"a"},
+        Token::Div,
+        token_ident! {"b"},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_comment_marker_in_string, r#"let url = "http://example.com"; // real comment"#, vec![
+        Token::Let,
+        token_ident! {"url"},
+        Token::Assign,
+        token_string! {"http://example.com"},
+        Token::Semicolon,
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_line_comment_spans() {
+        let (_, result) = Lexer::lexer_spanned_tokens(str_to_u8_slice("a // x\nb")).unwrap();
+        let spans = result.iter().map(|(_, span)| (span.start, span.end)).collect::<Vec<_>>();
+        assert_eq!(spans, vec![(0, 1), (7, 8), (8, 8)]);
+    }
+
+    check_tokens! {test_block_comment, "1 + /* two */ 3", vec![
+        Token::NumericLiteral(1),
+        Token::Plus,
+        Token::NumericLiteral(3),
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_block_comment_contents, "a /* 2 * 3 / 4, \"quoted */ b /**/ c /***/ d", vec![
+        token_ident! {"a"},
+        token_ident! {"b"},
+        token_ident! {"c"},
+        token_ident! {"d"},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_nested_block_comment, "a /* one /* two /* three */ */ still comment */ b", vec![
+        token_ident! {"a"},
+        token_ident! {"b"},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_nested_block_comment_with_quotes, "a /* let s = \"/* not a string */\"; 'x' */ b", vec![
+        token_ident! {"a"},
+        token_ident! {"b"},
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_unbalanced_nested_block_comment() {
+        let input = str_to_u8_slice("a /* outer /* inner */ b");
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::Unterminated(_))));
+        assert_eq!(err.input, &input[2..]);
+    }
+
+    check_tokens! {test_doc_comment, "/// Adds one.\n///\n///   Indented.\r\n  /// Last line\nfn inc(x) {}", vec![
+        Token::DocComment(String::from("Adds one.\n\n  Indented.\nLast line")),
+        Token::Function,
+        token_ident! {"inc"},
+        Token::LParenthesis,
+        token_ident! {"x"},
+        Token::RParenthesis,
+        Token::LBrace,
+        Token::RBrace,
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_doc_comments_split, "/// a\n\n/// b\nlet c; /// d\n// e\n//// f\n/// g", vec![
+        Token::DocComment(String::from("a")),
+        Token::DocComment(String::from("b")),
+        Token::Let,
+        token_ident! {"c"},
+        Token::Semicolon,
+        Token::DocComment(String::from("d")),
+        Token::DocComment(String::from("g")),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_multiline_block_comment() {
+        let commented = Lexer::lexer_tokens(str_to_u8_slice("let a = 1;\n/* let b = 2;\n   let c = 3; */\nlet d = a;")).unwrap().1;
+        let plain = Lexer::lexer_tokens(str_to_u8_slice("let a = 1;\nlet d = a;")).unwrap().1;
+        assert_eq!(commented, plain);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let input = str_to_u8_slice("let a = 1; /* let b = 2;\nlet c = 3;");
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::Unterminated(_))));
+        assert_eq!(err.input, &input[11..]);
+        assert_eq!(err.to_string(), "Unterminated block comment, expected closing */");
     }
 
     // basic tests
@@ -448,6 +1726,95 @@ mod tests {
         Token::EOF,
     ]}
 
+    check_tokens! {test_interpolated_string, r#""x is ${x + 1}!""#, vec![
+        Token::InterpolatedString(vec![
+            StringPart::Literal(String::from("x is ")),
+            StringPart::Expr(vec![token_ident! {"x"}, Token::Plus, Token::NumericLiteral(1)]),
+            StringPart::Literal(String::from("!")),
+        ]),
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_interpolated_string_adjacent, r#""${a}${ b }" "$a \${b} $""#, vec![
+        Token::InterpolatedString(vec![
+            StringPart::Expr(vec![token_ident! {"a"}]),
+            StringPart::Expr(vec![token_ident! {"b"}]),
+        ]),
+        token_string! {"$a ${b} $"},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_interpolated_string_nested, r#""${ f("}", "${n}") } { ${ {} } }""#, vec![
+        Token::InterpolatedString(vec![
+            StringPart::Expr(vec![
+                token_ident! {"f"},
+                Token::LParenthesis,
+                token_string! {"}"},
+                Token::Comma,
+                Token::InterpolatedString(vec![StringPart::Expr(vec![token_ident! {"n"}])]),
+                Token::RParenthesis,
+            ]),
+            StringPart::Literal(String::from(" { ")),
+            StringPart::Expr(vec![Token::LBrace, Token::RBrace]),
+            StringPart::Literal(String::from(" }")),
+        ]),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_interpolated_string_unterminated() {
+        let input = str_to_u8_slice(r#"let s = "a ${b + 1";"#);
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::Unterminated(_))));
+        assert_eq!(err.input, &input[11..]);
+        assert_eq!(err.to_string(), "Unterminated string interpolation, expected closing }");
+    }
+
+    check_tokens! {test_triple_quoted_string,
+        "let s = \"\"\"\n        first\n          second\n\n        third\n        \"\"\";",
+        vec![
+        Token::Let,
+        token_ident! {"s"},
+        Token::Assign,
+        token_string! {"first\n  second\n\nthird"},
+        Token::Semicolon,
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_triple_quoted_string_inline,
+        r#""""one line"""  """a
+          b""" """""""#,
+        vec![
+        token_string! {"one line"},
+        token_string! {"a\n          b"},
+        token_string! {""},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_triple_quoted_string_quotes,
+        r#""""
+            say "hi", ""twice"" and \"""
+            \x41 is A
+            """"#,
+        vec![
+        token_string! {"say \"hi\", \"\"twice\"\" and \"\"\"\nA is A"},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_triple_quoted_string_crlf, "\"\"\"\r\n    a\r\n    b\r\n    \"\"\"", vec![
+        token_string! {"a\nb"},
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_triple_quoted_string_unterminated() {
+        let input = str_to_u8_slice("let s = \"\"\"\n    abc\n    \"\";");
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::Unterminated(_))));
+        assert_eq!(err.input, &input[8..]);
+        assert_eq!(err.to_string(), "Unterminated string literal, expected closing \"\"\"");
+    }
+
     check_tokens! {test_char,
         r#"'a' 'b' 'c' '❤' '\'' '\"' '\\' '8' 'ß'"#,
         vec![
@@ -463,6 +1830,72 @@ mod tests {
         Token::EOF,
     ]}
 
+    check_tokens! {test_hex_escapes,
+        r#""\x41\x42" "a\x20b" '\x7f' '\x41' '\x00'"#,
+        vec![
+        token_string! {"AB"},
+        token_string! {"a b"},
+        Token::CharLiteral('\x7f'),
+        Token::CharLiteral('A'),
+        Token::CharLiteral('\0'),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_hex_escape_out_of_range() {
+        let input = str_to_u8_slice(r#"let s = "ab\xff";"#);
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidEscape(EscapeError::HexOutOfRange(0xff)))));
+        assert_eq!(err.input, &input[11..]);
+        assert_eq!(err.to_string(), r"\xff is out of range, \x escapes only cover ASCII (\x00 to \x7f); write \u{ff} for the character U+00FF");
+        let (_, suggested) = Lexer::lexer_tokens(str_to_u8_slice(r#""ab\u{ff}""#)).unwrap();
+        assert_eq!(suggested[0], token_string! {"abÿ"});
+    }
+
+    #[test]
+    fn test_hex_escape_truncated() {
+        for input in [r#""\x4""#, r#"'\x'"#, r#""\xg1""#] {
+            let err = lex_failure(str_to_u8_slice(input));
+            assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidEscape(EscapeError::TruncatedHex))));
+            assert_eq!(err.input, &str_to_u8_slice(input)[1..]);
+        }
+    }
+
+    check_tokens! {test_unicode_escape,
+        r#""\u{00E9}tude" "\u{41}\u{3bb}" '\u{1F600}' '\u{10ffff}' '\u{0}'"#,
+        vec![
+        token_string! {"étude"},
+        token_string! {"Aλ"},
+        Token::CharLiteral('😀'),
+        Token::CharLiteral('\u{10ffff}'),
+        Token::CharLiteral('\0'),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_unicode_escape_invalid_codepoint() {
+        for (input, value) in [(r#""a\u{D800}""#, 0xd800), (r#"'\u{110000}'"#, 0x110000)] {
+            let err = lex_failure(str_to_u8_slice(input));
+            assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidEscape(EscapeError::InvalidCodepoint(v))) if v == value));
+        }
+        assert_eq!(lex_failure(str_to_u8_slice(r#""\u{d800}""#)).to_string(), r"\u{d800} is not a character, surrogates and values above 10ffff are not allowed");
+    }
+
+    #[test]
+    fn test_unicode_escape_malformed() {
+        for (input, text) in [
+            (r#""ab\u{}""#, r"\u{}"),
+            (r#""ab\u41""#, r"\u"),
+            (r#""ab\u{41""#, r"\u{41"),
+            (r#""ab\u{1234567}""#, r"\u{1234567}"),
+            (r#""ab\u{4g}""#, r"\u{4"),
+        ] {
+            let err = lex_failure(str_to_u8_slice(input));
+            assert_eq!(err.input, &str_to_u8_slice(input)[3..]);
+            assert_eq!(err.to_string(), format!(r"Malformed unicode escape {}, expected \u{{...}} with 1 to 6 hex digits", text));
+        }
+    }
+
     check_tokens! {test_bool, "true false", vec![
         Token::BoolLiteral(true),
         Token::BoolLiteral(false),
@@ -487,6 +1920,116 @@ mod tests {
         Token::EOF,
     ]}
 
+    check_tokens! {test_dot_decimal_forms, "let a = .5; let b = 1.; c(-.25, 3.)", vec![
+        Token::Let,
+        token_ident! {"a"},
+        Token::Assign,
+        Token::DecimalLiteral(0.5),
+        Token::Semicolon,
+        Token::Let,
+        token_ident! {"b"},
+        Token::Assign,
+        Token::DecimalLiteral(1.0),
+        Token::Semicolon,
+        token_ident! {"c"},
+        Token::LParenthesis,
+        Token::DecimalLiteral(-0.25),
+        Token::Comma,
+        Token::DecimalLiteral(3.0),
+        Token::RParenthesis,
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_range_is_not_decimal, "1..5", vec![
+        Token::NumericLiteral(1),
+        Token::Illegal,
+        Token::Illegal,
+        Token::NumericLiteral(5),
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_member_access_is_not_decimal, "x.5 f().0 1.abs", vec![
+        token_ident! {"x"},
+        Token::Illegal,
+        Token::NumericLiteral(5),
+        token_ident! {"f"},
+        Token::LParenthesis,
+        Token::RParenthesis,
+        Token::Illegal,
+        Token::NumericLiteral(0),
+        Token::NumericLiteral(1),
+        Token::Illegal,
+        token_ident! {"abs"},
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_numeric_suffix, "let x = 10f; 10i -3f 1.5f (.5f)", vec![
+        Token::Let,
+        token_ident! {"x"},
+        Token::Assign,
+        Token::DecimalLiteral(10.0),
+        Token::Semicolon,
+        Token::NumericLiteral(10),
+        Token::DecimalLiteral(-3.0),
+        Token::DecimalLiteral(1.5),
+        Token::LParenthesis,
+        Token::DecimalLiteral(0.5),
+        Token::RParenthesis,
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_unknown_numeric_suffix() {
+        let input = str_to_u8_slice("let x = 10u8;");
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidSuffix(SuffixError::Unknown(ref s))) if s == "u8"));
+        assert_eq!(err.input, &input[10..]);
+        assert_eq!(err.to_string(), "Unknown numeric suffix `u8`, expected `i` or `f`");
+    }
+
+    #[test]
+    fn test_int_suffix_on_decimal() {
+        let input = str_to_u8_slice("1.5i");
+        let err = lex_failure(input);
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidSuffix(SuffixError::IntOnDecimal))));
+        assert_eq!(err.input, &input[3..]);
+    }
+
+    check_tokens! {test_number_limits, "9223372036854775807 -9223372036854775808 007 -0", vec![
+        Token::NumericLiteral(i64::MAX),
+        Token::NumericLiteral(i64::MIN),
+        Token::NumericLiteral(7),
+        Token::NumericLiteral(0),
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_number_overflow() {
+        for literal in ["9223372036854775808", "-9223372036854775809", "99999999999999999999999"] {
+            let err = convert_slice_to_number(literal.as_bytes()).unwrap_err();
+            assert!(matches!(err, ParseError::InvalidNumber(NumberError::Overflow(ref s)) if s == literal));
+        }
+    }
+
+    // The byte-level conversions agree with str::parse on every literal
+    // the number rules accept.
+    #[test]
+    fn test_number_conversion_matches_std() {
+        for literal in ["0", "-0", "42", "-42", "0000123", "9223372036854775807", "-9223372036854775808"] {
+            assert_eq!(convert_slice_to_number(literal.as_bytes()).ok(), literal.parse::<i64>().ok());
+        }
+        for literal in ["0.0", "-0.0", "1.5", "1.", ".5", "-.5", "123456789.987654321", "0.1"] {
+            let f = convert_slice_to_decimal(literal.as_bytes()).unwrap();
+            assert_eq!(f.to_bits(), literal.parse::<f64>().unwrap().to_bits());
+        }
+    }
+
+    check_tokens! {test_negative_zero_decimal, "-0.0 0.0", vec![
+        Token::DecimalLiteral(-0.0),
+        Token::DecimalLiteral(0.0),
+        Token::EOF,
+    ]}
+
     // TODO: Add more 
     check_tokens! {test_illegal, r#"" '' _"#, vec![
         Token::Illegal,
@@ -496,6 +2039,58 @@ mod tests {
         Token::EOF,
     ]}
 
+    // panic regression tests
+
+    check_tokens! {test_multi_char_literal, "'ab'", vec![
+        Token::Illegal,
+        token_ident! {"ab"},
+        Token::Illegal,
+        Token::EOF,
+    ]}
+
+    check_tokens! {test_overlong_char_literal, "'abcde'", vec![
+        Token::Illegal,
+        token_ident! {"abcde"},
+        Token::Illegal,
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_long_literals() {
+        let body = "a".repeat(1_000_000);
+        let input = format!("\"{}\" '{}", body, body);
+        let (_, result) = Lexer::lexer_tokens(str_to_u8_slice(&input)).unwrap();
+        assert_eq!(result, vec![
+            Token::StringLiteral(body.clone()),
+            Token::Illegal,
+            Token::Ident(body),
+            Token::EOF,
+        ]);
+    }
+
+    #[test]
+    fn test_deeply_nested_interpolation() {
+        let nested = |n: usize| format!("{}x{}", "\"${".repeat(n), "}\"".repeat(n));
+        assert!(Lexer::lexer_tokens(str_to_u8_slice(&nested(8))).is_ok());
+
+        let input = nested(10_000);
+        let err = lex_failure(str_to_u8_slice(&input));
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::TooDeeplyNested(_))));
+        assert_eq!(err.to_string(), "string interpolation nested deeper than 32 levels");
+    }
+
+    check_tokens! {test_escape_at_end_of_input, r#""\"#, vec![
+        Token::Illegal,
+        Token::Illegal,
+        Token::EOF,
+    ]}
+
+    #[test]
+    fn test_triple_quoted_escape_at_end_of_input() {
+        let err = lex_failure(str_to_u8_slice(r#""""\"#));
+        assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::Unterminated(_))));
+    }
+
     check_tokens! {test_empty, "", vec![
         Token::EOF,
     ]}
@@ -1,3 +1,19 @@
-pub mod tokens;
-pub mod lex;
-mod error;
\ No newline at end of file
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used))]
+
+// The submodules are an implementation detail; everything public is
+// re-exported here, and the common part again from crate::prelude.
+
+pub(crate) mod tokens;
+pub(crate) mod lex;
+pub(crate) mod span;
+pub(crate) mod diff;
+pub(crate) mod error;
+
+pub use self::diff::{token_diff, TokenChange, TokenDiff};
+pub use self::error::{
+    CharParseError, EscapeError, InvalidCharByteSequenceError, LexError, LexErrorKind, LocatedLexError,
+    NestingError, NumberError, ParseError, SpanError, SuffixError, UnterminatedError,
+};
+pub use self::lex::{Explanation, LexOutput, LexReport, LexWarning, Lexer, LexerOptions};
+pub use self::span::{LineIndex, Span, SpannedToken};
+pub use self::tokens::{format_decimal, KeywordTable, StringPart, Token, Tokens, CONTEXTUAL_KEYWORDS, KEYWORDS, RESERVED_WORDS};
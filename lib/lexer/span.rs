@@ -0,0 +1,225 @@
+use crate::lexer::error::SpanError;
+use crate::lexer::tokens::Token;
+
+// Byte range into the lexed input, end exclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        debug_assert!(start <= end, "inverted span {}..{}", start, end);
+        Span { start, end }
+    }
+
+    pub fn try_new(start: usize, end: usize) -> Result<Self, SpanError> {
+        if start <= end {
+            Ok(Span { start, end })
+        } else {
+            Err(SpanError::Inverted { start, end })
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    // The smallest span covering both.
+    pub fn merge(&self, other: Span) -> Span {
+        Span { start: self.start.min(other.start), end: self.end.max(other.end) }
+    }
+
+    // Moves the span by `delta` bytes, for offsets after an edit.
+    pub fn shift(&self, delta: isize) -> Result<Span, SpanError> {
+        let start = self.start.checked_add_signed(delta).ok_or(SpanError::Overflow)?;
+        let end = self.end.checked_add_signed(delta).ok_or(SpanError::Overflow)?;
+        Span::try_new(start, end)
+    }
+
+    // Like shift, but clamps at 0 and usize::MAX instead of failing.
+    pub fn saturating_shift(&self, delta: isize) -> Span {
+        Span {
+            start: self.start.saturating_add_signed(delta),
+            end: self.end.saturating_add_signed(delta),
+        }
+    }
+
+    // Whether the span lies within a text of `len` bytes.
+    pub fn fits(&self, len: usize) -> bool {
+        self.start <= self.end && self.end <= len
+    }
+}
+
+pub type SpannedToken = (Token, Span);
+
+// Maps byte offsets to 1-based line and column. A line ends after its
+// `\n`, so in a `\r\n` pair the `\r` belongs to the line it terminates.
+// Columns count characters, a tab being one column like any other.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LineIndex<'a> {
+    source: &'a [u8],
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a [u8]) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { source, line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // Offsets past the end are clamped to it.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let start = line.checked_sub(1).and_then(|i| self.line_starts.get(i)).copied().unwrap_or(0);
+        let chars = self.source.get(start..offset).unwrap_or_default()
+            .iter()
+            .filter(|b| (**b & 0xc0) != 0x80)
+            .count();
+        (line, chars + 1)
+    }
+
+    // The text of a 1-based line, without its `\n` or `\r\n`.
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let mut end = self.line_starts.get(line).map_or(self.source.len(), |next| next - 1);
+        if end > start && self.source.get(end - 1) == Some(&b'\r') && self.source.get(end) == Some(&b'\n') {
+            end -= 1;
+        }
+        Some(Span::new(start, end))
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use super::*;
+
+    #[test]
+    fn test_line_index() {
+        let index = LineIndex::new(b"ab\n\tc\n\nd");
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_col(0), (1, 1));
+        assert_eq!(index.line_col(2), (1, 3));
+        assert_eq!(index.line_col(3), (2, 1));
+        assert_eq!(index.line_col(4), (2, 2));
+        assert_eq!(index.line_col(6), (3, 1));
+        assert_eq!(index.line_col(7), (4, 1));
+        assert_eq!(index.line_col(100), (4, 2));
+        assert_eq!(index.line_span(2), Some(Span::new(3, 5)));
+        assert_eq!(index.line_span(3), Some(Span::new(6, 6)));
+        assert_eq!(index.line_span(4), Some(Span::new(7, 8)));
+        assert_eq!(index.line_span(0), None);
+        assert_eq!(index.line_span(5), None);
+    }
+
+    #[test]
+    fn test_line_index_crlf() {
+        let index = LineIndex::new(b"let a;\r\nlet b;\r\n");
+        assert_eq!(index.line_count(), 3);
+        assert_eq!(index.line_col(5), (1, 6));
+        assert_eq!(index.line_col(6), (1, 7));
+        assert_eq!(index.line_col(7), (1, 8));
+        assert_eq!(index.line_col(8), (2, 1));
+        assert_eq!(index.line_col(14), (2, 7));
+        assert_eq!(index.line_span(1), Some(Span::new(0, 6)));
+        assert_eq!(index.line_span(2), Some(Span::new(8, 14)));
+        assert_eq!(index.line_span(3), Some(Span::new(16, 16)));
+        // A lone carriage return is ordinary text.
+        assert_eq!(LineIndex::new(b"a\rb").line_col(3), (1, 4));
+        assert_eq!(LineIndex::new(b"a\r").line_span(1), Some(Span::new(0, 2)));
+    }
+
+    #[test]
+    fn test_line_index_counts_chars() {
+        let index = LineIndex::new("é❤\nx".as_bytes());
+        assert_eq!(index.line_col(5), (1, 3));
+        assert_eq!(index.line_col(6), (2, 1));
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(Span::try_new(2, 5), Ok(Span::new(2, 5)));
+        assert_eq!(Span::try_new(5, 5), Ok(Span::new(5, 5)));
+        assert_eq!(Span::try_new(5, 2), Err(SpanError::Inverted { start: 5, end: 2 }));
+    }
+
+    #[test]
+    fn test_shift() {
+        assert_eq!(Span::new(4, 6).shift(3), Ok(Span::new(7, 9)));
+        assert_eq!(Span::new(4, 6).shift(-4), Ok(Span::new(0, 2)));
+        assert_eq!(Span::new(4, 6).shift(-5), Err(SpanError::Overflow));
+        assert_eq!(Span::new(4, usize::MAX).shift(1), Err(SpanError::Overflow));
+        assert_eq!(Span::new(4, 6).saturating_shift(-5), Span::new(0, 1));
+    }
+
+    #[test]
+    fn test_merge() {
+        assert_eq!(Span::new(4, 6).merge(Span::new(1, 2)), Span::new(1, 6));
+        assert_eq!(Span::new(4, 6).merge(Span::new(5, 5)), Span::new(4, 6));
+    }
+
+    // An edit replaces `removed` bytes at `at` with `inserted` bytes.
+    // Spans before it stay put, spans after it shift, and spans
+    // overlapping it are dropped, as a re-lex would.
+    fn apply_edit(spans: &[Span], at: usize, removed: usize, inserted: usize) -> Vec<Span> {
+        let delta = inserted as isize - removed as isize;
+        spans.iter().filter_map(|span| {
+            if span.end <= at {
+                Some(*span)
+            } else if span.start >= at + removed {
+                Some(span.shift(delta).unwrap())
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn test_edits_keep_spans_valid(
+            len in 0usize..200,
+            raw_spans in prop::collection::vec((0usize..200, 0usize..20), 0..20),
+            edits in prop::collection::vec((0usize..200, 0usize..20, 0usize..20), 0..20),
+        ) {
+            let mut len = len;
+            let mut spans = raw_spans.into_iter()
+                .map(|(start, width)| Span::new(start.min(len), (start + width).min(len)))
+                .collect::<Vec<_>>();
+            for (at, removed, inserted) in edits {
+                let at = at.min(len);
+                let removed = removed.min(len - at);
+                spans = apply_edit(&spans, at, removed, inserted);
+                len = len - removed + inserted;
+                for span in &spans {
+                    prop_assert!(span.fits(len), "{:?} out of bounds for length {}", span, len);
+                }
+            }
+        }
+
+        #[test]
+        fn test_shift_round_trips(start in 0usize..1000, width in 0usize..100, delta in -1000isize..1000) {
+            let span = Span::new(start, start + width);
+            if let Ok(shifted) = span.shift(delta) {
+                prop_assert_eq!(shifted.len(), span.len());
+                prop_assert_eq!(shifted.shift(-delta), Ok(span));
+            } else {
+                prop_assert!((start as isize) + delta < 0);
+            }
+        }
+    }
+}
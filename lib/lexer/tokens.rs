@@ -1,10 +1,15 @@
 use std::ops::{RangeFull, RangeFrom, RangeTo, Range};
 use std::iter::Enumerate;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::{fmt, mem};
 
 use nom::*;
 
+use crate::lexer::span::{LineIndex, Span};
 
-#[derive(PartialEq, Debug, Clone)]
+
+#[derive(Debug, Clone)]
 pub enum Token {
     Illegal,
     EOF,
@@ -16,6 +21,10 @@ pub enum Token {
     NumericLiteral(i64),
     DecimalLiteral(f64),
     BoolLiteral(bool),
+    InterpolatedString(Vec<StringPart>),
+
+    // the text of consecutive `///` lines, markers stripped
+    DocComment(String),
 
     // operators
     Plus,
@@ -48,6 +57,12 @@ pub enum Token {
     Let,
     Mut,
 
+    // reserved for future use, see RESERVED_WORDS
+    ReservedWord(String),
+
+    // embedder-defined keyword, see KeywordTable
+    Custom(u16),
+
     // logic operations
     LogicAnd,
     LogicOr,
@@ -71,6 +86,94 @@ pub enum Token {
     RBracket,
 }
 
+// Words with no meaning yet that still cannot be used as identifiers, so
+// giving them one later does not break existing programs.
+pub const RESERVED_WORDS: [&str; 8] = ["struct", "enum", "match", "for", "const", "import", "type", "pub"];
+
+// Words that only mean something in certain positions, like `in` in a
+// `for` header or `as` after an expression. They lex as plain identifiers
+// so programs can keep using them as names, and the grammar asks for them
+// with Token::is_contextual.
+pub const CONTEXTUAL_KEYWORDS: [&str; 2] = ["in", "as"];
+
+impl Token {
+    // Whether this is the contextual keyword `word`, which is an
+    // identifier with exactly that spelling.
+    pub fn is_contextual(&self, word: &str) -> bool {
+        debug_assert!(CONTEXTUAL_KEYWORDS.contains(&word), "{:?} is not a contextual keyword", word);
+        matches!(self, Token::Ident(name) if name == word)
+    }
+}
+
+pub const KEYWORDS: [(&str, Token); 12] = [
+    ("let", Token::Let),
+    ("mut", Token::Mut),
+    ("fn", Token::Function),
+    ("if", Token::If),
+    ("elif", Token::ElseIf),
+    ("else", Token::Else),
+    ("while", Token::While),
+    ("return", Token::Return),
+    ("continue", Token::Continue),
+    ("break", Token::Break),
+    ("true", Token::BoolLiteral(true)),
+    ("false", Token::BoolLiteral(false)),
+];
+
+// The words that lex as something other than an identifier. The default
+// holds KEYWORDS and RESERVED_WORDS; embedders can add their own keywords
+// as Token::Custom or free up existing ones.
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeywordTable {
+    words: BTreeMap<String, Token>,
+}
+
+impl KeywordTable {
+    pub fn empty() -> Self {
+        KeywordTable { words: BTreeMap::new() }
+    }
+
+    pub fn add(&mut self, word: &str, token: Token) -> &mut Self {
+        self.words.insert(word.to_owned(), token);
+        self
+    }
+
+    pub fn remove(&mut self, word: &str) -> &mut Self {
+        self.words.remove(word);
+        self
+    }
+
+    pub fn get(&self, word: &str) -> Option<&Token> {
+        self.words.get(word)
+    }
+
+    // The token for an identifier-shaped word.
+    pub fn lookup(&self, word: String) -> Token {
+        self.words.get(&word).cloned().unwrap_or(Token::Ident(word))
+    }
+}
+
+impl Default for KeywordTable {
+    fn default() -> Self {
+        let mut table = KeywordTable::empty();
+        for (word, token) in KEYWORDS {
+            table.add(word, token);
+        }
+        for word in RESERVED_WORDS {
+            table.add(word, Token::ReservedWord(word.to_owned()));
+        }
+        table
+    }
+}
+
+// A chunk of an interpolated string: either literal text or the tokens of
+// an embedded `${...}` expression.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum StringPart {
+    Literal(String),
+    Expr(Vec<Token>),
+}
+
 // Tokens implementations
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -84,30 +187,71 @@ impl<'a> Tokens<'a> {
     pub fn new(init: &'a [Token]) -> Self {
         Tokens { tokens: init, start: 0, end: init.len() }
     }
+
+    // One token per line, with its index in the original stream, variant
+    // name and payload, for readable snapshots in tests and traces.
+    pub fn render(&self) -> String {
+        self.render_lines(|_| None)
+    }
+
+    // Like render, with the 1-based line:col of each token. `spans` is
+    // indexed like the original stream, as lexer_spanned_tokens returns it.
+    pub fn render_with_spans(&self, spans: &[Span], source: &[u8]) -> String {
+        let lines = LineIndex::new(source);
+        self.render_lines(|index| {
+            let (line, col) = lines.line_col(spans.get(index)?.start);
+            Some(format!("{}:{}", line, col))
+        })
+    }
+
+    // The whole window on one line: its index range, then the tokens.
+    pub fn render_compact(&self) -> String {
+        let tokens = self.tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<_>>();
+        format!("{}..{}: {}", self.start, self.end, tokens.join(" "))
+    }
+
+    fn render_lines(&self, position: impl Fn(usize) -> Option<String>) -> String {
+        let mut out = String::new();
+        for (i, token) in self.tokens.iter().enumerate() {
+            let index = self.start + i;
+            let debug = format!("{:?}", token);
+            let (kind, payload) = match debug.split_once('(') {
+                Some((kind, rest)) => (kind, rest.strip_suffix(')').unwrap_or(rest)),
+                None => (debug.as_str(), ""),
+            };
+            let line = match position(index) {
+                Some(position) => format!("{:>4} {:<7} {:<16} {}", index, position, kind, payload),
+                None => format!("{:>4} {:<16} {}", index, kind, payload),
+            };
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
 }
 
 impl<'a> InputTake for Tokens<'a> {
     #[inline]
     fn take(&self, count: usize) -> Self {
-        Tokens { 
-            tokens: &self.tokens[..count], 
-            start: 0, 
-            end: count 
-        }
+        let (prefix, _) = self.take_split(count);
+        prefix
     }
 
+    // Counts past the end are clamped instead of panicking. Both halves
+    // keep their positions in the original stream.
     #[inline]
     fn take_split(&self, count: usize) -> (Self, Self) {
-        let (prefix, suffix) = self.tokens.split_at(count);
+        let (prefix, suffix) = self.tokens.split_at(count.min(self.tokens.len()));
+        let middle = self.start + prefix.len();
         let first = Tokens {
             tokens: prefix,
-            start: 0,
-            end: prefix.len(),
+            start: self.start,
+            end: middle,
         };
         let second = Tokens {
             tokens: suffix,
-            start: 0,
-            end: suffix.len(),
+            start: middle,
+            end: self.end,
         };
         (first, second)
     }
@@ -134,7 +278,7 @@ impl<'a> Slice<Range<usize>> for Tokens<'a> {
 impl<'a> Slice<RangeFrom<usize>> for Tokens<'a> {
     #[inline]
     fn slice(&self, range: RangeFrom<usize>) -> Self {
-        self.slice(range.start..self.end - self.start)
+        self.slice(range.start..self.tokens.len())
     }
 }
 
@@ -188,9 +332,470 @@ impl<'a> InputIter for Tokens<'a> {
 
 // Token implementations
 
+// Decimals are compared and hashed by their bit pattern, so a NaN token
+// equals itself and -0.0 stays distinct from 0.0. Both matches list every
+// variant, so a new one does not compile until it is handled here.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Token::Ident(a), Token::Ident(b)) => a == b,
+            (Token::StringLiteral(a), Token::StringLiteral(b)) => a == b,
+            (Token::CharLiteral(a), Token::CharLiteral(b)) => a == b,
+            (Token::NumericLiteral(a), Token::NumericLiteral(b)) => a == b,
+            (Token::DecimalLiteral(a), Token::DecimalLiteral(b)) => a.to_bits() == b.to_bits(),
+            (Token::BoolLiteral(a), Token::BoolLiteral(b)) => a == b,
+            (Token::InterpolatedString(a), Token::InterpolatedString(b)) => a == b,
+            (Token::DocComment(a), Token::DocComment(b)) => a == b,
+            (Token::ReservedWord(a), Token::ReservedWord(b)) => a == b,
+            (Token::Custom(a), Token::Custom(b)) => a == b,
+            (Token::Ident(_), _) | (Token::StringLiteral(_), _) | (Token::CharLiteral(_), _)
+            | (Token::NumericLiteral(_), _) | (Token::DecimalLiteral(_), _) | (Token::BoolLiteral(_), _)
+            | (Token::InterpolatedString(_), _) | (Token::DocComment(_), _) | (Token::ReservedWord(_), _)
+            | (Token::Custom(_), _) => false,
+            (Token::Illegal, _) | (Token::EOF, _) | (Token::Plus, _) | (Token::Minus, _) | (Token::Div, _)
+            | (Token::Mult, _) | (Token::Modulo, _) | (Token::Equal, _) | (Token::Exp, _)
+            | (Token::NotEqual, _) | (Token::GreaterThanEqual, _) | (Token::LessThanEqual, _)
+            | (Token::GreaterThan, _) | (Token::LessThan, _) | (Token::Not, _) | (Token::Assign, _)
+            | (Token::FunctionReturn, _) | (Token::If, _) | (Token::ElseIf, _) | (Token::Else, _)
+            | (Token::While, _) | (Token::Function, _) | (Token::Return, _) | (Token::Break, _)
+            | (Token::Continue, _) | (Token::Let, _) | (Token::Mut, _) | (Token::LogicAnd, _)
+            | (Token::LogicOr, _) | (Token::BooleanAnd, _) | (Token::BooleanXor, _) | (Token::BooleanOr, _)
+            | (Token::LShift, _) | (Token::RShift, _) | (Token::Semicolon, _) | (Token::Colon, _)
+            | (Token::Comma, _) | (Token::LParenthesis, _) | (Token::RParenthesis, _) | (Token::LBrace, _)
+            | (Token::RBrace, _) | (Token::LBracket, _) | (Token::RBracket, _) => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Token::Ident(s) | Token::StringLiteral(s) | Token::ReservedWord(s) | Token::DocComment(s) => s.hash(state),
+            Token::CharLiteral(c) => c.hash(state),
+            Token::NumericLiteral(i) => i.hash(state),
+            Token::DecimalLiteral(f) => f.to_bits().hash(state),
+            Token::BoolLiteral(b) => b.hash(state),
+            Token::InterpolatedString(parts) => parts.hash(state),
+            Token::Custom(id) => id.hash(state),
+            Token::Illegal | Token::EOF | Token::Plus | Token::Minus | Token::Div | Token::Mult
+            | Token::Modulo | Token::Equal | Token::Exp | Token::NotEqual | Token::GreaterThanEqual
+            | Token::LessThanEqual | Token::GreaterThan | Token::LessThan | Token::Not | Token::Assign
+            | Token::FunctionReturn | Token::If | Token::ElseIf | Token::Else | Token::While | Token::Function
+            | Token::Return | Token::Break | Token::Continue | Token::Let | Token::Mut | Token::LogicAnd
+            | Token::LogicOr | Token::BooleanAnd | Token::BooleanXor | Token::BooleanOr | Token::LShift
+            | Token::RShift | Token::Semicolon | Token::Colon | Token::Comma | Token::LParenthesis
+            | Token::RParenthesis | Token::LBrace | Token::RBrace | Token::LBracket | Token::RBracket => {}
+        }
+    }
+}
+
+// Source spelling of a token, such that lexing the output gives the token
+// back. Non-finite decimals have no literal form and print as Rust does.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Illegal => write!(f, "<illegal>"),
+            Token::EOF => write!(f, "<eof>"),
+            Token::Ident(s) | Token::ReservedWord(s) => write!(f, "{}", s),
+            Token::StringLiteral(s) => {
+                write!(f, "\"")?;
+                write_escaped(f, s, '"')?;
+                write!(f, "\"")
+            }
+            Token::InterpolatedString(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Literal(s) => write_escaped(f, s, '"')?,
+                        StringPart::Expr(tokens) => {
+                            write!(f, "${{")?;
+                            for (i, token) in tokens.iter().enumerate() {
+                                if i > 0 {
+                                    write!(f, " ")?;
+                                }
+                                write!(f, "{}", token)?;
+                            }
+                            write!(f, "}}")?;
+                        }
+                    }
+                }
+                write!(f, "\"")
+            }
+            Token::CharLiteral(c) => {
+                write!(f, "'")?;
+                write_escaped(f, c.encode_utf8(&mut [0; 4]), '\'')?;
+                write!(f, "'")
+            }
+            Token::DocComment(text) => {
+                for (i, line) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    match line {
+                        "" => write!(f, "///")?,
+                        line => write!(f, "/// {}", line)?,
+                    }
+                }
+                Ok(())
+            }
+            Token::NumericLiteral(i) => write!(f, "{}", i),
+            Token::DecimalLiteral(d) => write!(f, "{}", format_decimal(*d)),
+            Token::BoolLiteral(b) => write!(f, "{}", b),
+            Token::Custom(id) => write!(f, "<custom {}>", id),
+            other => write!(f, "{}", syntax_of(other)),
+        }
+    }
+}
+
+// Shortest digits that parse back to the same f64, always with a decimal
+// point so the literal does not lex as an integer. Display for f64 never
+// switches to exponent notation, which Kaleido literals do not have.
+pub fn format_decimal(d: f64) -> String {
+    let s = d.to_string();
+    if d.is_finite() && !s.contains('.') {
+        s + ".0"
+    } else {
+        s
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str, quote: char) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '\\' | '$' => write!(f, "\\{}", c)?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            c if c == quote => write!(f, "\\{}", c)?,
+            // Other control characters would be invisible or move the
+            // terminal cursor, so they are written as escapes too.
+            c if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u32)?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+fn syntax_of(token: &Token) -> &'static str {
+    match token {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Div => "/",
+        Token::Mult => "*",
+        Token::Modulo => "%",
+        Token::Equal => "==",
+        Token::Exp => "**",
+        Token::NotEqual => "!=",
+        Token::GreaterThanEqual => ">=",
+        Token::LessThanEqual => "<=",
+        Token::GreaterThan => ">",
+        Token::LessThan => "<",
+        Token::Not => "!",
+        Token::Assign => "=",
+        Token::FunctionReturn => "->",
+        Token::If => "if",
+        Token::ElseIf => "elif",
+        Token::Else => "else",
+        Token::While => "while",
+        Token::Function => "fn",
+        Token::Return => "return",
+        Token::Break => "break",
+        Token::Continue => "continue",
+        Token::Let => "let",
+        Token::Mut => "mut",
+        Token::LogicAnd => "&&",
+        Token::LogicOr => "||",
+        Token::BooleanAnd => "&",
+        Token::BooleanXor => "^",
+        Token::BooleanOr => "|",
+        Token::LShift => "<<",
+        Token::RShift => ">>",
+        Token::Semicolon => ";",
+        Token::Colon => ":",
+        Token::Comma => ",",
+        Token::LParenthesis => "(",
+        Token::RParenthesis => ")",
+        Token::LBrace => "{",
+        Token::RBrace => "}",
+        Token::LBracket => "[",
+        Token::RBracket => "]",
+        Token::Illegal
+        | Token::EOF
+        | Token::Ident(_)
+        | Token::ReservedWord(_)
+        | Token::StringLiteral(_)
+        | Token::InterpolatedString(_)
+        | Token::DocComment(_)
+        | Token::CharLiteral(_)
+        | Token::NumericLiteral(_)
+        | Token::DecimalLiteral(_)
+        | Token::BoolLiteral(_)
+        | Token::Custom(_) => "",
+    }
+}
+
 impl InputLength for Token {
     #[inline]
     fn input_len(&self) -> usize {
         1
     }
-}
\ No newline at end of file
+}
+
+// tests
+
+#[cfg(test)]
+mod decimal_tests {
+    use std::collections::hash_map::DefaultHasher;
+    use super::*;
+
+    fn hash_of(token: &Token) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        token.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_nan_equals_itself() {
+        let nan = Token::DecimalLiteral(f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert_eq!(hash_of(&nan), hash_of(&nan.clone()));
+    }
+
+    #[test]
+    fn test_payload_and_variant_both_compared() {
+        let word = || String::from("match");
+        assert_eq!(Token::ReservedWord(word()), Token::ReservedWord(word()));
+        assert_ne!(Token::ReservedWord(word()), Token::Ident(word()));
+        assert_ne!(Token::DocComment(word()), Token::DocComment(String::from("other")));
+        assert_ne!(Token::Custom(1), Token::Custom(2));
+        assert_eq!(Token::Semicolon, Token::Semicolon);
+        assert_ne!(Token::Semicolon, Token::Colon);
+        assert_ne!(Token::Semicolon, Token::Custom(0));
+    }
+
+    #[test]
+    fn test_nan_payloads_differ() {
+        let a = Token::DecimalLiteral(f64::from_bits(0x7ff8_0000_0000_0001));
+        let b = Token::DecimalLiteral(f64::from_bits(0x7ff8_0000_0000_0002));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_negative_zero_is_distinct() {
+        let pos = Token::DecimalLiteral(0.0);
+        let neg = Token::DecimalLiteral(-0.0);
+        assert_ne!(pos, neg);
+        assert_ne!(hash_of(&pos), hash_of(&neg));
+    }
+
+    #[test]
+    fn test_infinity() {
+        assert_eq!(Token::DecimalLiteral(f64::INFINITY), Token::DecimalLiteral(f64::INFINITY));
+        assert_ne!(Token::DecimalLiteral(f64::INFINITY), Token::DecimalLiteral(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn test_decimal_is_not_numeric() {
+        assert_ne!(Token::DecimalLiteral(1.0), Token::NumericLiteral(1));
+        assert_ne!(Token::Ident(String::from("a")), Token::StringLiteral(String::from("a")));
+    }
+}
+
+#[cfg(test)]
+mod display_tests {
+    use proptest::prelude::*;
+    use super::*;
+    use crate::lexer::lex::Lexer;
+
+    fn relex(token: &Token) -> Vec<Token> {
+        let source = token.to_string();
+        Lexer::lexer_tokens(source.as_bytes()).unwrap().1
+    }
+
+    fn assert_round_trip(token: Token) {
+        assert_eq!(relex(&token), vec![token.clone(), Token::EOF], "{} did not round-trip", token);
+    }
+
+    #[test]
+    fn test_control_character_display() {
+        assert_eq!(Token::StringLiteral(String::from("a\x01")).to_string(), "\"a\\x01\"");
+        assert_eq!(Token::StringLiteral(String::from("\x1b[0m\x7f")).to_string(), "\"\\x1b[0m\\x7f\"");
+        assert_eq!(Token::CharLiteral('\u{9b}').to_string(), "'\\u{9b}'");
+    }
+
+    #[test]
+    fn test_decimal_display() {
+        assert_eq!(Token::DecimalLiteral(1.0).to_string(), "1.0");
+        assert_eq!(Token::DecimalLiteral(-0.0).to_string(), "-0.0");
+        assert_eq!(Token::DecimalLiteral(0.1).to_string(), "0.1");
+        assert_eq!(Token::DecimalLiteral(1e21).to_string(), "1000000000000000000000.0");
+    }
+
+    #[test]
+    fn test_decimal_round_trip_edge_cases() {
+        for d in [0.0, -0.0, 0.1, 1.0 / 3.0, f64::MAX, f64::MIN, f64::MIN_POSITIVE, f64::from_bits(1), -f64::from_bits(1), f64::EPSILON] {
+            assert_round_trip(Token::DecimalLiteral(d));
+        }
+    }
+
+    #[test]
+    fn test_literal_round_trip() {
+        assert_round_trip(Token::StringLiteral(String::from("say \"hi\" \\ ${x}")));
+        assert_round_trip(Token::CharLiteral('\''));
+        assert_round_trip(Token::CharLiteral('❤'));
+        assert_round_trip(Token::StringLiteral(String::from("tab\there\r\n\0")));
+        assert_round_trip(Token::CharLiteral('\t'));
+        assert_round_trip(Token::StringLiteral(String::from("bell\x07 esc\x1b del\x7f nel\u{85}")));
+        assert_round_trip(Token::CharLiteral('\x01'));
+        assert_round_trip(Token::NumericLiteral(i64::MIN));
+        assert_round_trip(Token::InterpolatedString(vec![
+            StringPart::Literal(String::from("a ")),
+            StringPart::Expr(vec![Token::Ident(String::from("b")), Token::Plus, Token::NumericLiteral(1)]),
+        ]));
+        assert_round_trip(Token::DocComment(String::from("Adds one.\n\n  Indented.")));
+        assert_round_trip(Token::LShift);
+        assert_round_trip(Token::ElseIf);
+    }
+
+    proptest! {
+        #[test]
+        fn test_decimal_round_trip(bits in any::<u64>()) {
+            let d = f64::from_bits(bits);
+            prop_assume!(d.is_finite());
+            let token = Token::DecimalLiteral(d);
+            prop_assert_eq!(relex(&token), vec![token, Token::EOF]);
+        }
+
+        #[test]
+        fn test_text_literal_round_trip(
+            s in "[\\x00-\\x1f\\x7f-\\u{9f}a-z \"'\\\\$❤]*",
+            c in prop_oneof![any::<char>(), (0u8..0x20).prop_map(char::from), (0x7fu8..0xa0).prop_map(char::from)],
+        ) {
+            for token in [Token::StringLiteral(s), Token::CharLiteral(c)] {
+                prop_assert_eq!(relex(&token), vec![token.clone(), Token::EOF]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod size_tests {
+    use std::mem::size_of;
+    use super::*;
+
+    // The largest payload is a String (24 bytes on 64-bit targets), plus
+    // the discriminant rounded up to alignment. A variant needing more than
+    // that should box its payload instead of growing every token.
+    #[test]
+    fn test_token_size() {
+        assert!(size_of::<Token>() <= 32, "Token grew to {} bytes", size_of::<Token>());
+    }
+
+    // Tokens is handed around by value in every nom combinator of the
+    // parser: a slice reference plus the start/end bookkeeping.
+    #[test]
+    fn test_tokens_size() {
+        assert!(size_of::<Tokens>() <= 32, "Tokens grew to {} bytes", size_of::<Tokens>());
+    }
+}
+
+#[cfg(test)]
+mod tokens_tests {
+    use super::*;
+    use crate::lexer::lex::Lexer;
+
+    const SAMPLE: &[u8] = b"fn foo(bar: baz) -> int {\n    let a = 5 + 3;\n    let b = a * 0.5;\n    return b;\n}\n";
+
+    fn lex_sample() -> (Vec<Token>, Vec<Span>) {
+        let (_, spanned) = Lexer::lexer_spanned_tokens(SAMPLE).unwrap();
+        spanned.into_iter().unzip()
+    }
+
+    #[test]
+    fn test_window_positions() {
+        let (tokens, _) = lex_sample();
+        let all = Tokens::new(&tokens);
+        let (prefix, suffix) = all.take_split(10);
+        assert_eq!((prefix.start, prefix.end), (0, 10));
+        assert_eq!((suffix.start, suffix.end), (10, 29));
+        let (inner, rest) = suffix.take_split(7);
+        assert_eq!((inner.start, inner.end), (10, 17));
+        assert_eq!((rest.start, rest.end), (17, 29));
+        let tail = rest.slice(2..);
+        assert_eq!((tail.start, tail.end, tail.input_len()), (19, 29, 10));
+        assert_eq!(tail.tokens.first(), Some(&Token::Assign));
+        assert_eq!(tail.slice(..3).end, 22);
+    }
+
+    #[test]
+    fn test_render() {
+        let (tokens, _) = lex_sample();
+        let window = Tokens::new(&tokens).slice(17..29);
+        assert_eq!(window.render(), concat!(
+            "  17 Let\n",
+            "  18 Ident            \"b\"\n",
+            "  19 Assign\n",
+            "  20 Ident            \"a\"\n",
+            "  21 Mult\n",
+            "  22 DecimalLiteral   0.5\n",
+            "  23 Semicolon\n",
+            "  24 Return\n",
+            "  25 Ident            \"b\"\n",
+            "  26 Semicolon\n",
+            "  27 RBrace\n",
+            "  28 EOF\n",
+        ));
+    }
+
+    #[test]
+    fn test_render_with_spans() {
+        let (tokens, spans) = lex_sample();
+        let window = Tokens::new(&tokens).take_split(10).1.take(7);
+        assert_eq!(window.render_with_spans(&spans, SAMPLE), concat!(
+            "  10 2:5     Let\n",
+            "  11 2:9     Ident            \"a\"\n",
+            "  12 2:11    Assign\n",
+            "  13 2:13    NumericLiteral   5\n",
+            "  14 2:15    Plus\n",
+            "  15 2:17    NumericLiteral   3\n",
+            "  16 2:18    Semicolon\n",
+        ));
+    }
+
+    #[test]
+    fn test_render_compact() {
+        let (tokens, _) = lex_sample();
+        let window = Tokens::new(&tokens).slice(10..17);
+        assert_eq!(window.render_compact(), "10..17: Let Ident(\"a\") Assign NumericLiteral(5) Plus NumericLiteral(3) Semicolon");
+        let doc = [Token::DocComment(String::from("two\nlines"))];
+        assert!(!Tokens::new(&doc).render_compact().contains('\n'));
+    }
+
+    #[test]
+    fn test_take_past_end() {
+        let source = [Token::Let, Token::Semicolon];
+        let tokens = Tokens::new(&source);
+        assert_eq!(tokens.take(5).tokens, &source);
+        let (prefix, suffix) = tokens.take_split(3);
+        assert_eq!(prefix.input_len(), 2);
+        assert_eq!(suffix.input_len(), 0);
+    }
+
+    #[test]
+    fn test_contextual_keywords_are_idents() {
+        let table = KeywordTable::default();
+        for word in CONTEXTUAL_KEYWORDS {
+            assert_eq!(table.lookup(word.to_owned()), Token::Ident(word.to_owned()));
+            assert!(!RESERVED_WORDS.contains(&word));
+            assert!(!KEYWORDS.iter().any(|(keyword, _)| *keyword == word));
+        }
+        assert!(Token::Ident(String::from("in")).is_contextual("in"));
+        assert!(!Token::Ident(String::from("inx")).is_contextual("in"));
+        assert!(!Token::StringLiteral(String::from("as")).is_contextual("as"));
+    }
+}
@@ -1,5 +1,7 @@
 extern crate nom;
 
 pub mod lexer;
-pub mod parser;
-pub mod repl;
\ No newline at end of file
+pub(crate) mod parser;
+pub mod prelude;
+pub(crate) mod repl;
+pub mod testgen;
\ No newline at end of file
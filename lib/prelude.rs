@@ -0,0 +1,8 @@
+// The types most users of the crate need, in one import:
+// `use kaleido_lib::prelude::*`. The rest of the lexer's public items are
+// in crate::lexer.
+
+pub use crate::lexer::{LexError, LexErrorKind, LocatedLexError, ParseError};
+pub use crate::lexer::{Lexer, LexerOptions, LexOutput, LexWarning};
+pub use crate::lexer::{Span, SpannedToken};
+pub use crate::lexer::{KeywordTable, StringPart, Token, Tokens};
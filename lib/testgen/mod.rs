@@ -0,0 +1,218 @@
+// Deterministic generator for realistic Kaleido programs, for benchmarks,
+// fuzzing and differential tests. The same options always produce the same
+// source text, on every platform.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GenOptions {
+    pub seed: u64,
+    pub functions: usize,
+    // Statements per function body, counting nested ones.
+    pub statements: usize,
+    pub max_depth: usize,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions { seed: 0, functions: 10, statements: 20, max_depth: 3 }
+    }
+}
+
+// SplitMix64, small and good enough to vary the shape of the output.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items.get(self.below(items.len())).copied().unwrap_or_default()
+    }
+}
+
+const ARITH_OPS: [&str; 5] = ["+", "-", "*", "/", "%"];
+const CMP_OPS: [&str; 6] = ["==", "!=", "<", ">", "<=", ">="];
+const WORDS: [&str; 6] = ["alpha", "beta", "gamma", "delta", "hello", "world"];
+
+struct Gen {
+    rng: Rng,
+    out: String,
+    // Statements still to emit in the current function.
+    budget: usize,
+    max_depth: usize,
+    locals: Vec<String>,
+}
+
+impl Gen {
+    fn line(&mut self, indent: usize, text: &str) {
+        self.out.push_str(&"    ".repeat(indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn operand(&mut self) -> String {
+        match self.rng.below(6) {
+            0 | 1 if !self.locals.is_empty() => {
+                let i = self.rng.below(self.locals.len());
+                self.locals.get(i).cloned().unwrap_or_default()
+            }
+            2 => format!("{}.{}", self.rng.below(1000), self.rng.below(100)),
+            _ => self.rng.below(10_000).to_string(),
+        }
+    }
+
+    fn arith(&mut self) -> String {
+        let lhs = self.operand();
+        let rhs = self.operand();
+        format!("{} {} {}", lhs, self.rng.pick(&ARITH_OPS), rhs)
+    }
+
+    fn cond(&mut self) -> String {
+        let lhs = self.arith();
+        let rhs = self.operand();
+        format!("{} {} {}", lhs, self.rng.pick(&CMP_OPS), rhs)
+    }
+
+    fn literal(&mut self) -> String {
+        match self.rng.below(5) {
+            0 => format!("\"{} {}\"", self.rng.pick(&WORDS), self.rng.below(100)),
+            1 => format!("'{}'", (b'a' + self.rng.below(26) as u8) as char),
+            2 => String::from(if self.rng.below(2) == 0 { "true" } else { "false" }),
+            3 => {
+                let inner = self.arith();
+                format!("\"{} ${{{}}}\"", self.rng.pick(&WORDS), inner)
+            }
+            _ => self.arith(),
+        }
+    }
+
+    fn block(&mut self, indent: usize, in_loop: bool) {
+        let len = 1 + self.rng.below(4);
+        for _ in 0..len {
+            if self.budget == 0 {
+                break;
+            }
+            self.statement(indent, in_loop);
+        }
+    }
+
+    fn statement(&mut self, indent: usize, in_loop: bool) {
+        self.budget -= 1;
+        let nested = indent <= self.max_depth && self.budget > 0;
+        match self.rng.below(10) {
+            0 | 1 if nested => {
+                let cond = self.cond();
+                self.line(indent, &format!("while ({}) {{", cond));
+                self.block(indent + 1, true);
+                self.line(indent, "}");
+            }
+            2 | 3 if nested => {
+                let cond = self.cond();
+                self.line(indent, &format!("if ({}) {{", cond));
+                self.block(indent + 1, in_loop);
+                if self.rng.below(2) == 0 {
+                    let cond = self.cond();
+                    self.line(indent, &format!("}} elif ({}) {{", cond));
+                    self.block(indent + 1, in_loop);
+                }
+                self.line(indent, "} else {");
+                self.block(indent + 1, in_loop);
+                self.line(indent, "}");
+            }
+            4 if in_loop => {
+                let keyword = if self.rng.below(2) == 0 { "break" } else { "continue" };
+                self.line(indent, &format!("{};", keyword));
+            }
+            5 | 6 if !self.locals.is_empty() => {
+                let i = self.rng.below(self.locals.len());
+                let name = self.locals.get(i).cloned().unwrap_or_default();
+                let value = self.arith();
+                self.line(indent, &format!("{} = {};", name, value));
+            }
+            _ => {
+                let name = format!("v{}", self.locals.len());
+                let value = self.literal();
+                let mutability = if self.rng.below(3) == 0 { "mut " } else { "" };
+                self.line(indent, &format!("let {}{} = {};", mutability, name, value));
+                self.locals.push(name);
+            }
+        }
+    }
+
+    fn function(&mut self, index: usize, statements: usize) {
+        self.locals = vec![String::from("a"), String::from("b")];
+        self.budget = statements;
+        self.line(0, &format!("fn f{}(a: int, b: int) -> int {{", index));
+        while self.budget > 0 {
+            self.statement(1, false);
+        }
+        let result = self.arith();
+        self.line(1, &format!("return {};", result));
+        self.line(0, "}");
+        self.out.push('\n');
+    }
+}
+
+pub fn generate(options: GenOptions) -> String {
+    let mut gen = Gen {
+        rng: Rng(options.seed),
+        out: String::new(),
+        budget: 0,
+        max_depth: options.max_depth,
+        locals: vec![],
+    };
+    for index in 0..options.functions {
+        gen.function(index, options.statements);
+    }
+    gen.out
+}
+
+// tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex::Lexer;
+    use crate::lexer::tokens::Token;
+
+    fn lex(source: &str) -> Vec<Token> {
+        let (rest, tokens) = Lexer::lexer_tokens(source.as_bytes()).unwrap();
+        assert!(rest.is_empty());
+        tokens
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let options = GenOptions { seed: 42, ..GenOptions::default() };
+        assert_eq!(generate(options), generate(options));
+        assert_ne!(generate(options), generate(GenOptions { seed: 43, ..options }));
+    }
+
+    #[test]
+    fn test_size() {
+        let source = generate(GenOptions { functions: 3, statements: 50, ..GenOptions::default() });
+        assert_eq!(source.matches("\nfn ").count() + 1, 3);
+        // Every statement takes at least a line, plus signature, return and closing brace.
+        assert!(source.lines().filter(|l| !l.is_empty()).count() >= 3 * (50 + 3));
+    }
+
+    #[test]
+    fn test_generated_programs_lex() {
+        for seed in 0..20 {
+            let source = generate(GenOptions { seed, ..GenOptions::default() });
+            let tokens = lex(&source);
+            assert!(!tokens.contains(&Token::Illegal), "illegal token in seed {}:\n{}", seed, source);
+            let opened = tokens.iter().filter(|t| **t == Token::LBrace).count();
+            let closed = tokens.iter().filter(|t| **t == Token::RBrace).count();
+            assert_eq!(opened, closed);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+use std::process::Command;
+
+// Builds and runs an example through cargo, returning its stdout.
+fn run_example(name: &str, fixture: &str) -> String {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(fixture);
+    let output = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--example", name, "--"])
+        .arg(fixture)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run cargo");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_tokenize() {
+    let expected = "\
+0..2\tfn
+3..7\thalf
+7..8\t(
+8..9\tx
+9..10\t:
+11..14\tint
+14..15\t)
+16..18\t->
+19..22\tint
+23..24\t{
+29..32\tlet
+33..34\ts
+35..36\t=
+37..48\t\"x is ${x}\"
+48..49\t;
+54..60\treturn
+61..62\tx
+63..64\t/
+65..67\t2.0
+67..68\t;
+69..70\t}
+71..71\t<eof>
+";
+    assert_eq!(run_example("tokenize", "tokenize.kld"), expected);
+}
@@ -0,0 +1,103 @@
+// Runs every program in tests/invalid and matches the errors it produces
+// against the `//~ CODE` markers in the file, rustc style: `//~ K0104`
+// expects the error on the marker's own line, and each `^` in `//~^^ K0104`
+// moves the expectation one line up.
+
+use std::fs;
+use std::path::Path;
+
+use kaleido_lib::prelude::{Lexer, LocatedLexError};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+struct Expected {
+    line: usize,
+    code: String,
+}
+
+fn parse_markers(source: &str) -> Vec<Expected> {
+    let mut markers = vec![];
+    for (index, text) in source.lines().enumerate() {
+        let Some(at) = text.find("//~") else {
+            continue;
+        };
+        let rest = &text[at + 3..];
+        let carets = rest.chars().take_while(|c| *c == '^').count();
+        let code = rest[carets..].trim();
+        assert!(carets <= index, "marker on line {} points before the file start", index + 1);
+        markers.push(Expected { line: index + 1 - carets, code: code.to_owned() });
+    }
+    markers
+}
+
+fn line_of(source: &[u8], offset: usize) -> usize {
+    source[..offset.min(source.len())].iter().filter(|b| **b == b'\n').count() + 1
+}
+
+fn produced(source: &[u8]) -> Vec<Expected> {
+    let (_, errors) = Lexer::lex_with_recovery(source);
+    errors.into_iter()
+        .map(|err| LocatedLexError::from_nom(source, nom::Err::Failure(err)))
+        .map(|err| Expected { line: line_of(source, err.offset), code: err.kind.code().to_owned() })
+        .collect()
+}
+
+// Returns the expectations with no matching error and the errors with no
+// matching expectation.
+fn compare(mut expected: Vec<Expected>, mut actual: Vec<Expected>) -> (Vec<Expected>, Vec<Expected>) {
+    expected.sort();
+    actual.sort();
+    let mut missing = vec![];
+    for marker in expected {
+        match actual.iter().position(|found| *found == marker) {
+            Some(i) => {
+                actual.remove(i);
+            }
+            None => missing.push(marker),
+        }
+    }
+    (missing, actual)
+}
+
+#[test]
+fn test_marker_parsing() {
+    let markers = parse_markers("a\nb //~ K0001\n//~^^ K0002\n");
+    assert_eq!(markers, vec![
+        Expected { line: 2, code: String::from("K0001") },
+        Expected { line: 1, code: String::from("K0002") },
+    ]);
+}
+
+#[test]
+fn test_compare_reports_both_directions() {
+    let expected = vec![Expected { line: 1, code: String::from("K0104") }, Expected { line: 2, code: String::from("K0105") }];
+    let actual = vec![Expected { line: 1, code: String::from("K0104") }, Expected { line: 3, code: String::from("K0105") }];
+    let (missing, unexpected) = compare(expected, actual);
+    assert_eq!(missing, vec![Expected { line: 2, code: String::from("K0105") }]);
+    assert_eq!(unexpected, vec![Expected { line: 3, code: String::from("K0105") }]);
+}
+
+#[test]
+fn test_invalid_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/invalid");
+    let mut paths = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "kld"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(paths.len() >= 12, "corpus shrank to {} programs", paths.len());
+
+    let mut failures = vec![];
+    for path in paths {
+        let source = fs::read(&path).unwrap();
+        let markers = parse_markers(&String::from_utf8_lossy(&source));
+        assert!(!markers.is_empty(), "{} has no //~ markers", path.display());
+        let (missing, unexpected) = compare(markers, produced(&source));
+        for m in missing {
+            failures.push(format!("{}:{}: expected {}, not reported", path.display(), m.line, m.code));
+        }
+        for u in unexpected {
+            failures.push(format!("{}:{}: unexpected {}", path.display(), u.line, u.code));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
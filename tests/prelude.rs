@@ -0,0 +1,29 @@
+// Names every item of the prelude, so removing or renaming one fails to
+// compile here before it breaks users.
+
+use kaleido_lib::prelude::{
+    KeywordTable, LexError, LexErrorKind, LexOutput, LexWarning, Lexer, LexerOptions, LocatedLexError, ParseError,
+    Span, SpannedToken, StringPart, Token, Tokens,
+};
+
+#[test]
+fn test_prelude_items() {
+    let source = b"let s = \"${a}\"";
+    let options = LexerOptions { warn_missing_final_newline: true, keywords: KeywordTable::default(), ..LexerOptions::default() };
+    let (_, output): (_, LexOutput) = Lexer::lex_with_options(source, options).unwrap();
+    assert_eq!(output.warnings, vec![LexWarning::MissingFinalNewline(Span::new(source.len(), source.len()))]);
+
+    let spanned: &[SpannedToken] = &output.tokens;
+    let tokens = spanned.iter().map(|(token, _)| token.clone()).collect::<Vec<Token>>();
+    assert!(matches!(tokens.get(3), Some(Token::InterpolatedString(parts)) if matches!(parts.first(), Some(StringPart::Expr(_)))));
+    assert_eq!(Tokens::new(&tokens).tokens.len(), 5);
+
+    let source = b"let a = 0b2;";
+    let err: LexError<&[u8]> = match Lexer::lexer_tokens(source) {
+        Err(nom::Err::Failure(err)) => err,
+        other => panic!("expected a failure, got {:?}", other),
+    };
+    assert!(matches!(err.kind, LexErrorKind::Parse(ParseError::InvalidNumber(_))));
+    let located = LocatedLexError::from_nom(source, nom::Err::Failure(err));
+    assert_eq!(located.offset, 8);
+}
@@ -0,0 +1,187 @@
+// Dumps the public items declared under lib/ and compares the dump with
+// tests/public_api.txt, so a change to the crate's surface shows up as a
+// failing test and as a diff of that file in review. Set UPDATE_SNAPSHOTS=1
+// to rewrite the file after an intended change.
+//
+// The dump is read from the source text rather than from rustdoc: every
+// `pub` declaration and re-export, the variants of public enums, derives,
+// and impl blocks that are trait impls or have public members. It relies on
+// the layout the crate is written in: items at column 0, members indented
+// by four spaces, and tests at the end of each file after `#[cfg(test)]`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT: &str = "tests/public_api.txt";
+
+fn source_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            source_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+fn balance(text: &str) -> i32 {
+    text.chars()
+        .map(|c| match c {
+            '(' | '[' | '{' | '<' => 1,
+            ')' | ']' | '}' | '>' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+// Joins a declaration that spans several lines. `open` allows it to end in
+// the `{` of a body, for fns, structs and impls.
+fn declaration<'a>(first: &'a str, lines: &mut impl Iterator<Item = &'a str>, open: bool) -> String {
+    let mut text = first.trim().to_owned();
+    loop {
+        let depth = balance(&text.replace("->", ""));
+        let done = match text.chars().last() {
+            Some(';' | ',') => depth == 0,
+            Some('{') => open && depth == 1,
+            Some('}') => depth == 0,
+            _ => false,
+        };
+        let Some(line) = (!done).then(|| lines.next()).flatten() else {
+            break;
+        };
+        text.push(' ');
+        text.push_str(line.trim());
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn signature(text: &str) -> String {
+    if text.starts_with("pub use") {
+        return text.replace("{ ", "{").replace(", }", "}").trim_end_matches(';').to_owned();
+    }
+    let cut = if text.starts_with("pub const") || text.starts_with("pub static") {
+        text.find(" = ")
+    } else {
+        text.find(" {")
+    };
+    let text = cut.map_or(text, |at| &text[..at]);
+    text.trim_end_matches([';', ',']).to_owned()
+}
+
+struct Block {
+    header: String,
+    // A struct, enum or trait, listed even without public members.
+    item: bool,
+    variants: bool,
+    trait_impl: bool,
+    members: Vec<String>,
+}
+
+fn dump_file(source: &str, out: &mut Vec<String>) {
+    let mut lines = source.lines().take_while(|line| !line.starts_with("#[cfg(test)]"));
+    let mut derive = None;
+    let mut block: Option<Block> = None;
+    let mut in_body = false;
+    while let Some(line) = lines.next() {
+        if line == "}" {
+            if let Some(block) = block.take() {
+                if block.item || block.trait_impl || !block.members.is_empty() {
+                    out.push(format!("    {}", block.header));
+                    out.extend(block.members.iter().map(|member| format!("        {}", member)));
+                }
+            }
+            in_body = false;
+        } else if line.starts_with("#[derive(") {
+            derive = Some(line.to_owned());
+        } else if line.starts_with("pub ") || line.starts_with("impl") {
+            let text = declaration(line, &mut lines, !line.starts_with("pub use"));
+            let header = signature(&text);
+            if let Some(derive) = derive.take() {
+                out.push(format!("    {}", derive));
+            }
+            let opens_block = text.ends_with('{');
+            if opens_block && !header.starts_with("pub fn") {
+                block = Some(Block {
+                    item: header.starts_with("pub "),
+                    variants: header.starts_with("pub enum"),
+                    trait_impl: header.starts_with("impl") && header.contains(" for "),
+                    header,
+                    members: vec![],
+                });
+            } else if header.starts_with("impl") {
+                // A one-line impl, like `impl Eq for Token {}`.
+                if header.contains(" for ") {
+                    out.push(format!("    {}", header));
+                }
+            } else {
+                out.push(format!("    {}", header));
+                in_body = opens_block;
+            }
+        } else if !line.starts_with([' ', '#', '/']) && !line.is_empty() {
+            // A private item; its derive is not part of the surface.
+            derive = None;
+            in_body = line.ends_with('{');
+        } else if let (Some(block), false) = (block.as_mut(), in_body) {
+            let member = line.strip_prefix("    ").filter(|member| !member.starts_with(' '));
+            match member {
+                Some(member) if member.starts_with("pub ") => {
+                    let text = declaration(member, &mut lines, true);
+                    block.members.push(signature(&text));
+                    in_body = text.ends_with('{');
+                }
+                Some(member) if block.variants && !member.starts_with("//") && !member.starts_with('#') => {
+                    let text = declaration(member, &mut lines, false);
+                    block.members.push(text.trim_end_matches(',').to_owned());
+                }
+                Some(member) if member.ends_with('{') => in_body = true,
+                _ => {}
+            }
+        } else if line == "    }" {
+            in_body = false;
+        }
+    }
+}
+
+fn public_api() -> String {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("lib");
+    let mut files = vec![];
+    source_files(&root, &mut files);
+    let mut dump = String::new();
+    for path in files {
+        let mut items = vec![];
+        dump_file(&fs::read_to_string(&path).unwrap(), &mut items);
+        if items.is_empty() {
+            continue;
+        }
+        let name = path.strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/");
+        dump.push_str(&name);
+        dump.push('\n');
+        for item in items {
+            dump.push_str(&item);
+            dump.push('\n');
+        }
+    }
+    dump
+}
+
+#[test]
+fn test_public_api() {
+    let snapshot = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+    let actual = public_api();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&snapshot, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&snapshot).unwrap_or_default();
+    let added = actual.lines().filter(|line| !expected.lines().any(|old| old == *line)).collect::<Vec<_>>();
+    let removed = expected.lines().filter(|line| !actual.lines().any(|new| new == *line)).collect::<Vec<_>>();
+    assert!(
+        actual == expected,
+        "public API differs from {}; rerun with UPDATE_SNAPSHOTS=1 if the change is intended\nadded:\n{}\nremoved:\n{}",
+        SNAPSHOT,
+        added.join("\n"),
+        removed.join("\n"),
+    );
+}